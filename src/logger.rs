@@ -5,9 +5,11 @@ use std::path::{Path, PathBuf};
 
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serenity::model::channel::{Channel, Message};
+use serenity::model::channel::{Channel, Message, Reaction};
+use serenity::model::gateway::Ready;
 use serenity::model::guild::Member;
 use serenity::model::id::{ChannelId, GuildId, MessageId};
+use serenity::model::interactions::Interaction;
 use serenity::model::prelude::User;
 use serenity::prelude::*;
 use serenity::Result;
@@ -139,6 +141,8 @@ impl EventHandler for Logger {
             Ok(())
         }
         .await;
+
+        crate::tetris::source_deleted(&ctx, channel, message).await;
     }
 
     async fn message_update(
@@ -148,9 +152,10 @@ impl EventHandler for Logger {
         _new: Option<Message>,
         event: serenity::model::event::MessageUpdateEvent,
     ) {
-        if event.content.is_none() {
-            return;
-        }
+        let content = match &event.content {
+            Some(content) => content.clone(),
+            None => return,
+        };
 
         let _: Result<()> = async {
             let mut data = String::new();
@@ -162,7 +167,7 @@ impl EventHandler for Logger {
                     .edited_timestamp
                     .unwrap_or_else(chrono::Utc::now)
                     .to_rfc3339(),
-                escape(&event.content.unwrap())
+                escape(&content)
             )?;
 
             let mut log = self.get_log_file(&ctx, event.channel_id).await?;
@@ -170,6 +175,25 @@ impl EventHandler for Logger {
             Ok(())
         }
         .await;
+
+        crate::tetris::source_edited(&ctx, event.channel_id, event.id, &content).await;
+    }
+
+    async fn ready(&self, ctx: Context, _ready: Ready) {
+        crate::tetris::register_slash_commands(&ctx).await;
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        crate::tetris::interaction(&ctx, interaction).await;
+    }
+
+    // Both count as a press on `-play` boards, so players never have to clear their reactions.
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        crate::tetris::play_reaction(&ctx, &reaction).await;
+    }
+
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        crate::tetris::play_reaction(&ctx, &reaction).await;
     }
 
     async fn guild_member_addition(&self, ctx: Context, server: GuildId, new_member: Member) {
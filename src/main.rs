@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use serenity::client::bridge::gateway::GatewayIntents;
 use serenity::framework::standard::macros::{command, group};
 use serenity::framework::standard::{Args, CommandResult, StandardFramework};
+use serenity::http::Http;
 use serenity::model::channel::Message;
 use serenity::model::id::{ChannelId, GuildId, RoleId};
 use serenity::prelude::*;
@@ -19,19 +20,37 @@ async fn main() {
         .configure(|c| c.prefix("-"))
         .normal_message(tetris::normal_message)
         .group(&tetris::TETRIS_GROUP)
+        .group(&tetris::FUMENLIBRARY_GROUP)
+        .group(&tetris::DAILYPUZZLE_GROUP)
         .group(&CONFIGURATION_GROUP);
 
-    let mut client = Client::builder(std::fs::read_to_string(".token").unwrap().trim())
+    let token = std::fs::read_to_string(".token").unwrap();
+    let token = token.trim();
+    // Slash commands are registered under the application id. `.application-id` can give it;
+    // otherwise Discord is asked for it.
+    let application_id = match std::fs::read_to_string(".application-id") {
+        Ok(id) => id.trim().parse().unwrap(),
+        Err(_) => {
+            let http = Http::new_with_token(token);
+            http.get_current_application_info().await.unwrap().id.0
+        }
+    };
+    let mut client = Client::builder(token)
+        .application_id(application_id)
         .intents(
             GatewayIntents::GUILD_MEMBERS
                 | GatewayIntents::GUILD_MESSAGES
-                | GatewayIntents::DIRECT_MESSAGES,
+                | GatewayIntents::GUILD_MESSAGE_REACTIONS
+                | GatewayIntents::DIRECT_MESSAGES
+                | GatewayIntents::DIRECT_MESSAGE_REACTIONS,
         )
         .framework(framework)
         .event_handler(logger::Logger::new())
         .await
         .unwrap();
 
+    tokio::spawn(tetris::daily_puzzles(client.cache_and_http.http.clone()));
+
     if let Err(e) = client.start().await {
         println!("{:?}", e);
     }
@@ -60,16 +79,33 @@ async fn save_config() {
     serde_json::to_writer(writer, &*guard).unwrap();
 }
 
+async fn store_muted_role(guild: GuildId, role: RoleId) {
+    config(guild).await.muted_role = role;
+    save_config().await;
+}
+
+async fn store_admin_channel(guild: GuildId, channel: ChannelId) {
+    config(guild).await.admin_channel = channel;
+    save_config().await;
+}
+
+async fn store_puzzle_channel(guild: GuildId, channel: ChannelId) {
+    config(guild).await.puzzle_channel = Some(channel);
+    save_config().await;
+}
+
 #[derive(Serialize, Deserialize, Default)]
 struct Config {
     muted_role: RoleId,
     admin_channel: ChannelId,
+    #[serde(default)]
+    puzzle_channel: Option<ChannelId>,
 }
 
 #[group]
 #[only_in(guilds)]
 #[required_permissions(ADMINISTRATOR)]
-#[commands(set_muted_role, set_admin_channel)]
+#[commands(set_muted_role, set_admin_channel, set_puzzle_channel)]
 pub struct Configuration;
 
 #[command]
@@ -110,11 +146,7 @@ async fn set_muted_role(ctx: &Context, msg: &Message, mut args: Args) -> Command
         }
     };
 
-    let mut config = config(guild).await;
-    config.muted_role = role_id;
-    drop(config);
-
-    save_config().await;
+    store_muted_role(guild, role_id).await;
 
     Ok(())
 }
@@ -134,11 +166,27 @@ async fn set_admin_channel(ctx: &Context, msg: &Message, _args: Args) -> Command
         }
     };
 
-    let mut config = config(guild).await;
-    config.admin_channel = msg.channel_id;
-    drop(config);
+    store_admin_channel(guild, msg.channel_id).await;
 
-    save_config().await;
+    Ok(())
+}
+
+#[command]
+async fn set_puzzle_channel(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let guild = match msg.guild_id {
+        Some(v) => v,
+        None => {
+            msg.channel_id
+                .say(
+                    &ctx,
+                    "Could not find what server this is. I am very confused.",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    store_puzzle_channel(guild, msg.channel_id).await;
 
     Ok(())
 }
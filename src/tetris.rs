@@ -1,111 +1,1556 @@
 use serenity::prelude::*;
 use serenity::framework::standard::macros::{ hook, group, command };
 use serenity::framework::standard::{ CommandResult, Args };
-use serenity::model::channel::Message;
+use serenity::model::channel::{ Message, Reaction, ReactionType };
 use serenity::http::AttachmentType;
+use serenity::model::id::{ ChannelId, MessageId, UserId };
 use regex::Regex;
 use fumen::Fumen;
 use lazy_static::lazy_static;
 use libtetris::{ Board };
+use cold_clear::evaluation::{ Evaluator, Standard };
+
+mod bounded;
+mod breakdown;
+mod cache;
+mod cheese;
+mod compare;
+mod daily;
+mod diagram;
+mod evaluator;
+mod export;
+mod finesse;
+mod garbage;
+mod jstris;
+mod library;
+mod map;
+mod play;
+mod progress;
+mod reach;
+mod replay;
+mod replies;
+mod render;
+mod rotation;
+mod queue;
+mod scheduler;
+mod setups;
+mod slash;
+mod stats;
+mod tetrio;
+mod versus;
+
+lazy_static! {
+    static ref EXTRACT_OPTIONS: Regex = Regex::new(
+        r"([\w._]+)=(-?[\w._]+)"
+    ).unwrap();
+}
+
+#[group]
+#[commands(
+    cold_clear_analysis, finesse_check, reachability, versus, demo, cheese, setup, play,
+    to_tetrio, from_tetrio, to_wiki, to_grid, compare, evaluate
+)]
+pub struct Tetris;
+
+#[command]
+#[aliases("cc")]
+async fn cold_clear_analysis(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    analyse(ctx, msg.channel_id, msg.author.id, args.rest()).await
+}
+
+/// Runs a `-cc` analysis of `text` for `user`, replying in `channel`. `/cc` shares it.
+pub async fn analyse(ctx: &Context, channel: ChannelId, user: UserId, text: &str) -> CommandResult {
+    let fumen_data = match text.split_whitespace().next() {
+        Some("cancel") => {
+            let message = match scheduler::SCHEDULER.cancel(user) {
+                0 => "You have no analyses to cancel.",
+                _ => "Cancelling your analyses."
+            };
+            channel.say(&ctx.http, message).await?;
+            return Ok(())
+        }
+        Some(data) => data,
+        None => {
+            channel.say(&ctx.http, "Please pass a fumen to analyse").await?;
+            return Ok(())
+        }
+    };
+    let (fumen, options) = match extract_fumen(fumen_data).await {
+        Some(data) => data,
+        None => {
+            channel.say(&ctx.http, "Invalid fumen").await?;
+            return Ok(())
+        }
+    };
+    let rs = match rotation::RotationSystem::from_options(options) {
+        Ok(rs) => rs,
+        Err(e) => {
+            channel.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let evaluator = match evaluator::from_options("", options) {
+        Ok(evaluator) => evaluator,
+        Err(e) => {
+            channel.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let options = options.to_owned();
+    if fumen.pages.len() != 1 {
+        channel.say(&ctx.http, "Fumen should have 1 page and a queue comment.").await?;
+        return Ok(())
+    }
+    let page = &fumen.pages[0];
+    let (hold, current, pattern) = match parse_quiz(page) {
+        Ok(quiz) => quiz,
+        Err(e) => {
+            channel.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let field = to_libtetris_field(&page.field);
+    if field.iter().any(|&r| r == [true; 10]) {
+        channel.say(&ctx.http, "Fumen contains a complete row.").await?;
+        return Ok(())
+    }
+    let mut garbage = match garbage::Schedule::parse(&options, &page.garbage_row) {
+        Ok(garbage) => garbage,
+        Err(e) => {
+            channel.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    if !garbage.is_empty() && !pattern.is_literal() {
+        channel.say(&ctx.http, "Incoming garbage needs a fixed queue.").await?;
+        return Ok(())
+    }
+    let new_board = |next: &[libtetris::Piece]| {
+        let mut board = Board::new_with_state(field, Default::default(), hold, false, 0);
+        board.add_next_piece(current);
+        for &p in next {
+            board.add_next_piece(p);
+        }
+        board
+    };
+
+    let export_json = EXTRACT_OPTIONS.captures_iter(&options).any(|caps| {
+        caps.get(1).unwrap().as_str() == "json" && caps.get(2).unwrap().as_str() == "yes"
+    });
+    let cache_key = match garbage.hole_seed() {
+        Some(seed) => cache::key("cc", &fumen, &format!("{} seed={}", options, seed)),
+        None => cache::key("cc", &fumen, &options)
+    };
+    let summary_key = format!("{}.txt", cache_key);
+    let json_key = format!("{}.json", cache_key);
+    if pattern.is_literal() {
+        if let Some(gif) = cache::CACHE.get(&cache_key).await {
+            let summary = cache::CACHE.get(&summary_key).await
+                .and_then(|s| String::from_utf8(s).ok())
+                .unwrap_or_default();
+            let mut files = vec![AttachmentType::Bytes {
+                data: gif.into(),
+                filename: "fumen.gif".into()
+            }];
+            if export_json {
+                if let Some(json) = cache::CACHE.get(&json_key).await {
+                    files.push(json_attachment(json));
+                }
+            }
+            channel.send_files(&ctx.http, files, |f| f.content(summary)).await?;
+            return Ok(())
+        }
+    }
+
+    let ticket = match acquire_job(ctx, channel, user).await? {
+        Some(ticket) => ticket,
+        None => return Ok(())
+    };
+
+    if !pattern.is_literal() {
+        return queue_variations(
+            ctx, channel, &ticket, page.field, &pattern, &options, new_board
+        ).await
+    }
+
+    let board = new_board(&pattern.enumerate(1).unwrap()[0]);
+    let shadow = board.clone();
+    let count = (board.next_queue().count() + (hold.is_some() as usize) - 1).min(40);
+
+    let mut progress = progress::Progress::start(
+        ctx, channel, &format!("Running Cold Clear, placed 0/{}", count)
+    ).await?;
+
+    println!("Running Cold Clear...");
+
+    let cc = cold_clear::Interface::launch(board, cold_clear::Options {
+        speculate: false,
+        pcloop: None,
+        ..Default::default()
+    }, evaluator.clone(), None);
+
+    let settings = LineSettings {
+        count, rs, evaluator: &evaluator, garbage: &mut garbage, next_piece: || None
+    };
+    let line = play_line(ctx, &ticket, &mut progress, &cc, shadow, page.field, settings).await?;
+    let (mut fumen, stats, placements) = match line {
+        Some(line) => line,
+        None => return Ok(())
+    };
+
+    let mut summary = stats.summary();
+    // Random garbage holes can only be reproduced with their seed.
+    if let Some(seed) = garbage.hole_seed() {
+        summary += &format!("; holes: seed={}", seed);
+    }
+    let last_page = fumen.add_page();
+    last_page.comment = Some(summary.clone());
+    let summary = match fumen_link(&fumen) {
+        Some(link) => format!("{}\n<{}>", summary, link),
+        None => summary
+    };
+    let summary = match rs {
+        rotation::RotationSystem::Srs => summary,
+        rs => format!(
+            "Cold Clear only searches with SRS, so these moves were found with SRS; the inputs \
+            are shown for {}.\n{}", rs.name(), summary
+        )
+    };
+
+    let meter = if garbage.is_empty() {
+        None
+    } else {
+        Some(garbage.meter(fumen.pages.len()))
+    };
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen_with_meter(fumen, &options, meter.as_deref())
+    ).await.unwrap();
+    match gif {
+        Ok(gif) => {
+            cache::CACHE.insert(&cache_key, gif.clone()).await;
+            cache::CACHE.insert(&summary_key, summary.clone().into_bytes()).await;
+            if export_json {
+                let json = export::to_json(&placements);
+                cache::CACHE.insert(&json_key, json.clone()).await;
+                progress.finish_with(ctx, gif, vec![json_attachment(json)], Some(summary)).await?;
+            } else {
+                progress.finish(ctx, gif, Some(summary)).await?;
+            }
+        }
+        Err(e) => progress.fail(ctx, format!("Failed to render the analysis: {}", e)).await?
+    }
+
+    Ok(())
+}
+
+/// The `json=yes` attachment of `-cc`, listing every placement of the analysis.
+fn json_attachment(json: Vec<u8>) -> AttachmentType<'static> {
+    AttachmentType::Bytes {
+        data: json.into(),
+        filename: "analysis.json".into()
+    }
+}
+
+/// How `play_line` plays a line of placements.
+struct LineSettings<'a, F> {
+    count: usize,
+    /// The rotation system the inputs are described in.
+    rs: rotation::RotationSystem,
+    /// Scores each placement for export.
+    evaluator: &'a Standard,
+    garbage: &'a mut garbage::Schedule,
+    /// Feeds the queue after each move.
+    next_piece: F,
+}
+
+/// Lets Cold Clear play `count` pieces from `shadow`, building a fumen page with inputs, clear
+/// statistics and search info for each placement. Each placement is also recorded for export.
+/// Garbage from `garbage` rises before the placements it is due at.
+///
+/// Returns `None` if the job was cancelled, which is reported in the status message.
+async fn play_line(
+    ctx: &Context, ticket: &scheduler::Ticket, progress: &mut progress::Progress,
+    cc: &cold_clear::Interface, mut shadow: Board, field: [[fumen::CellColor; 10]; 23],
+    settings: LineSettings<'_, impl FnMut() -> Option<libtetris::Piece>>
+) -> serenity::Result<Option<(Fumen, stats::Stats, Vec<export::Placement>)>> {
+    let LineSettings { count, rs, evaluator, garbage, mut next_piece } = settings;
+    let mut fumen = Fumen::default();
+    let first_page = fumen.add_page();
+    first_page.field = field;
+    let mut placements = vec![];
+
+    let mut nodes = 0;
+    let mut stats = stats::Stats::default();
+    for placed in 0..count {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        if ticket.is_cancelled() {
+            progress.fail(ctx, format!("Analysis cancelled after {}/{} placements.", placed, count))
+                .await?;
+            return Ok(None)
+        }
+        let rows = garbage.raise(placed, &mut shadow);
+        if !rows.is_empty() {
+            cc.reset(shadow.get_field(), shadow.b2b_bonus, shadow.combo);
+        }
+        cc.suggest_next_move(garbage.incoming(placed));
+        tokio::task::yield_now().await;
+        if let Some((mv, info)) = cc.block_next_move() {
+            cc.play_next_move(mv.expected_location);
+            // Keep the shadow board's queue and hold in step with Cold Clear's.
+            if let Some(current) = shadow.advance_queue() {
+                if mv.hold && shadow.hold(current).is_none() {
+                    shadow.advance_queue();
+                }
+            }
+            if let Some(piece) = next_piece() {
+                cc.add_next_piece(piece);
+                shadow.add_next_piece(piece);
+            }
+            let mut comment = if rs == rotation::RotationSystem::Srs {
+                finesse::describe_move(&mv)
+            } else {
+                // Cold Clear searches with SRS, so find the inputs again under the chosen system.
+                let hold = if mv.hold { "hold, " } else { "" };
+                match finesse::finesse(rs, &shadow, mv.expected_location) {
+                    Some(inputs) => format!("{}{}", hold, finesse::describe_inputs(&inputs)),
+                    None => format!("{}not reachable under {}", hold, rs.name())
+                }
+            };
+            let lock = shadow.lock_piece(mv.expected_location);
+            let (value, reward) = evaluator.evaluate(
+                &lock, &shadow, 0, mv.expected_location.kind.0
+            );
+            placements.push(export::Placement::new(
+                mv.expected_location, mv.hold, &lock, &info, value.value + reward.value
+            ));
+            if let Some(clear) = stats.record(&lock) {
+                comment += &format!("; {}", clear);
+            }
+            let page = fumen.add_page();
+            garbage::insert(&mut page.field, &rows);
+            page.piece = Some(to_fumen(mv.expected_location));
+            if let cold_clear::Info::Normal(info) = info {
+                nodes += info.nodes as u64;
+                comment += &format!("; {}n, {}d", info.nodes, info.depth);
+            }
+            page.comment = Some(comment);
+            progress.update(ctx, format!(
+                "Running Cold Clear, placed {}/{}, {} nodes",
+                placed + 1, count, progress::format_count(nodes)
+            )).await?;
+        } else {
+            break;
+        }
+    }
+    Ok(Some((fumen, stats, placements)))
+}
+
+/// Runs Cold Clear on concrete queues drawn from `pattern` and reports how its first move depends
+/// on the pieces it couldn't have known about.
+///
+/// Every queue is analyzed when there are at most `limit=` (default 20) of them, otherwise
+/// `samples=` (default 20) queues are drawn using `seed=`.
+async fn queue_variations(
+    ctx: &Context, channel: ChannelId, ticket: &scheduler::Ticket,
+    field: [[fumen::CellColor; 10]; 23],
+    pattern: &queue::Pattern, options: &str, new_board: impl Fn(&[libtetris::Piece]) -> Board
+) -> CommandResult {
+    let mut limit = 20;
+    let mut samples = 20;
+    let mut seed = None;
+    for caps in EXTRACT_OPTIONS.captures_iter(options) {
+        let value = caps.get(2).unwrap().as_str();
+        match caps.get(1).unwrap().as_str() {
+            "limit" => if let Ok(v) = value.parse() {
+                limit = v;
+            }
+            "samples" => if let Ok(v) = value.parse() {
+                samples = v;
+            }
+            "seed" => seed = value.parse().ok(),
+            _ => {}
+        }
+    }
+    limit = limit.min(MAX_QUEUE_VARIATIONS);
+    samples = samples.min(MAX_QUEUE_VARIATIONS);
+    // `analyse` has already reported invalid evaluator settings.
+    let evaluator = evaluator::from_options("", options).unwrap_or_default();
+
+    let (queues, description) = match pattern.enumerate(limit) {
+        Some(queues) => {
+            let description = format!("all {} queues", queues.len());
+            (queues, description)
+        }
+        None => {
+            let seed = seed.unwrap_or_else(queue::random_seed);
+            let mut rng = queue::Rng::new(seed);
+            let queues = (0..samples).map(|_| pattern.sample(&mut rng)).collect();
+            let description = format!(
+                "{} of {} queues sampled with seed={}", samples, pattern.queue_count(), seed
+            );
+            (queues, description)
+        }
+    };
+
+    let mut progress = progress::Progress::start(
+        ctx, channel, &format!("Running Cold Clear on {}, analyzed 0/{}", description, queues.len())
+    ).await?;
+
+    println!("Running Cold Clear on {}...", description);
+
+    let mut outcomes: Vec<(libtetris::FallingPiece, Vec<String>)> = vec![];
+    for (analyzed, queue) in queues.iter().enumerate() {
+        let cc = cold_clear::Interface::launch(new_board(queue), cold_clear::Options {
+            speculate: false,
+            pcloop: None,
+            ..Default::default()
+        }, evaluator.clone(), None);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        if ticket.is_cancelled() {
+            progress.fail(ctx, format!(
+                "Analysis cancelled after {}/{} queues.", analyzed, queues.len()
+            )).await?;
+            return Ok(())
+        }
+        progress.update(ctx, format!(
+            "Running Cold Clear on {}, analyzed {}/{}", description, analyzed, queues.len()
+        )).await?;
+        cc.suggest_next_move(0);
+        tokio::task::yield_now().await;
+        let location = match cc.block_next_move() {
+            Some((mv, _)) => mv.expected_location,
+            None => continue
+        };
+        let name = queue::queue_string(queue);
+        match outcomes.iter_mut().find(|(l, _)| same_placement(*l, location)) {
+            Some((_, names)) => names.push(name),
+            None => outcomes.push((location, vec![name]))
+        }
+    }
+
+    if outcomes.is_empty() {
+        progress.fail(ctx, "Cold Clear could not find a move.".to_owned()).await?;
+        return Ok(())
+    }
+    outcomes.sort_by_key(|(_, names)| std::cmp::Reverse(names.len()));
+
+    let mut fumen = Fumen::default();
+    let first_page = fumen.add_page();
+    first_page.field = field;
+    first_page.comment = Some(description.clone());
+    for (location, names) in &outcomes {
+        let page = fumen.add_page();
+        page.field = field;
+        page.piece = Some(to_fumen(*location));
+        page.comment = Some(format!("{}/{}: {}", names.len(), queues.len(), names.join(", ")));
+    }
+
+    let summary = if outcomes.len() == 1 {
+        format!("Cold Clear's first move is the same for {}.", description)
+    } else {
+        let mut summary = format!(
+            "Cold Clear's first move depends on the queue ({} different moves for {}):",
+            outcomes.len(), description
+        );
+        for (i, (_, names)) in outcomes.iter().enumerate() {
+            let shown = names.iter().take(8).cloned().collect::<Vec<_>>().join(", ");
+            let more = if names.len() > 8 { ", ..." } else { "" };
+            summary += &format!("\n{}. {} queues: {}{}", i + 1, names.len(), shown, more);
+        }
+        summary
+    };
+
+    let options = options.to_owned();
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(fumen, &options)
+    ).await.unwrap();
+    match gif {
+        Ok(gif) => {
+            progress.finish(ctx, gif, Some(summary)).await?;
+        }
+        Err(e) => progress.fail(ctx, format!("Failed to render the analysis: {}", e)).await?
+    }
+
+    Ok(())
+}
+
+/// Takes a slot in the analysis scheduler, keeping the caller informed of their queue position.
+///
+/// Returns `None` if the caller already has too many jobs or cancelled while waiting.
+async fn acquire_job(
+    ctx: &Context, channel: ChannelId, user: UserId
+) -> serenity::Result<Option<scheduler::Ticket>> {
+    let ticket = match scheduler::SCHEDULER.enqueue(user) {
+        Ok(ticket) => ticket,
+        Err(scheduler::Rejected::TooManyJobs(max)) => {
+            channel.say(&ctx.http, format!(
+                "You can only have {} analyses running or queued. Use `-cc cancel` to stop them.",
+                max
+            )).await?;
+            return Ok(None)
+        }
+    };
+    if let Some(mut position) = ticket.position() {
+        let queued = |position| format!("Queued for analysis, position {}.", position);
+        let mut status = channel.say(&ctx.http, queued(position)).await?;
+        while let Some(new_position) = ticket.wait_for_change().await {
+            if new_position != position {
+                position = new_position;
+                status.edit(&ctx, |m| m.content(queued(position))).await?;
+            }
+        }
+        status.delete(&ctx).await?;
+    }
+    if ticket.is_cancelled() {
+        channel.say(&ctx.http, "Analysis cancelled.").await?;
+        return Ok(None)
+    }
+    Ok(Some(ticket))
+}
+
+const MAX_QUEUE_VARIATIONS: usize = 100;
+
+/// Reads a `#Q=[hold](current)queue` quiz comment, where the queue may be a pattern.
+fn parse_quiz(
+    page: &fumen::Page
+) -> Result<(Option<libtetris::Piece>, libtetris::Piece, queue::Pattern), String> {
+    lazy_static! {
+        static ref QUEUE_SELECTOR: Regex = Regex::new(
+            r"^#Q=\[([IOTJLSZ]?)\]\(([IOTJLSZ])\)(.*)$"
+        ).unwrap();
+    }
+    let caps = match page.comment.as_ref().and_then(|c| QUEUE_SELECTOR.captures(c)) {
+        Some(caps) => caps,
+        None => return Err("Fumen should have 1 page and a queue comment.".to_owned())
+    };
+    let hold = caps.get(1).unwrap();
+    let hold = hold.as_str().chars().next().and_then(from_char);
+    let current = caps.get(2).unwrap().as_str().chars().next().and_then(from_char).unwrap();
+    let pattern = queue::Pattern::parse(caps.get(3).unwrap().as_str())
+        .map_err(|e| format!("Invalid queue pattern: {}", e))?;
+    Ok((hold, current, pattern))
+}
+
+/// A link to view `fumen` in the fumen editor, if it fits in a Discord message.
+fn fumen_link(fumen: &Fumen) -> Option<String> {
+    let link = format!("https://harddrop.com/fumen/?{}", fumen.encode());
+    if link.len() > 1800 {
+        None
+    } else {
+        Some(link)
+    }
+}
+
+/// Whether two placements fill the same cells with the same piece.
+fn same_placement(a: libtetris::FallingPiece, b: libtetris::FallingPiece) -> bool {
+    let mut a_cells = a.cells();
+    let mut b_cells = b.cells();
+    a_cells.sort();
+    b_cells.sort();
+    a.kind.0 == b.kind.0 && a_cells == b_cells
+}
+
+/// Lists the minimal-input finesse for every placement in a fumen. Inputs given after the fumen
+/// (e.g. `-finesse v115@... DL CW HD`) are checked against the first placement.
+#[command]
+#[aliases("finesse")]
+async fn finesse_check(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let fumen_data = match args.trimmed().current() {
+        Some(data) => data.to_owned(),
+        None => {
+            msg.channel_id.say(&ctx.http, "Please pass a fumen to check").await?;
+            return Ok(())
+        }
+    };
+    args.advance();
+    let mut played = vec![];
+    for token in args.rest().split(|c: char| c == ',' || c.is_whitespace()) {
+        if token.is_empty() {
+            continue
+        }
+        match finesse::Input::parse(token) {
+            Some(input) => played.push(input),
+            None => {
+                msg.channel_id.say(&ctx.http, format!("Unknown input `{}`", token)).await?;
+                return Ok(())
+            }
+        }
+    }
+    let (fumen, options) = match extract_fumen(&fumen_data).await {
+        Some(data) => data,
+        None => {
+            msg.channel_id.say(&ctx.http, "Invalid fumen").await?;
+            return Ok(())
+        }
+    };
+    let rs = match rotation::RotationSystem::from_options(options) {
+        Ok(rs) => rs,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+
+    let mut report = String::new();
+    let mut first = true;
+    for (i, page) in fumen.pages.iter().enumerate() {
+        let piece = match page.piece {
+            Some(piece) => piece,
+            None => continue
+        };
+        let board = Board::new_with_state(
+            to_libtetris_field(&page.field), Default::default(), None, false, 0
+        );
+        let target = to_libtetris(piece);
+        let mut below = target;
+        let line = if board.obstructed(&target) {
+            "placement overlaps the field".to_owned()
+        } else if below.shift(&board, 0, -1) {
+            "piece is floating".to_owned()
+        } else {
+            match finesse::finesse(rs, &board, target) {
+                Some(inputs) => {
+                    let mut line = format!(
+                        "{} ({} inputs)", finesse::describe_inputs(&inputs), inputs.len()
+                    );
+                    if first && !played.is_empty() {
+                        let mut falling = rs.spawn(target.kind.0, &board);
+                        if let Some(falling) = &mut falling {
+                            for &input in &played {
+                                input.apply(falling, &board, rs);
+                            }
+                            falling.sonic_drop(&board);
+                        }
+                        let inputs_used = played.len()
+                            + (played.last() != Some(&finesse::Input::HardDrop)) as usize;
+                        line += &match falling {
+                            Some(f) if !same_placement(f, target) =>
+                                "\n  your inputs don't reach this placement".to_owned(),
+                            None => "\n  the piece can't spawn".to_owned(),
+                            _ if inputs_used > inputs.len() => format!(
+                                "\n  your {} inputs are {} more than needed",
+                                inputs_used, inputs_used - inputs.len()
+                            ),
+                            _ => "\n  your inputs are optimal".to_owned()
+                        };
+                    }
+                    line
+                }
+                None => format!("unreachable from spawn under {}", rs.name())
+            }
+        };
+        first = false;
+        report += &format!("Page {}: {}\n", i + 1, line);
+    }
+    if report.is_empty() {
+        report = "Fumen has no placements to check.".to_owned();
+    }
+    msg.channel_id.say(&ctx.http, report).await?;
+    Ok(())
+}
+
+/// Decides whether the placement on a fumen page can be reached from spawn, rendering the path if
+/// it can and the kick tests that get in the way if it can't. `rs=` picks the rotation system.
+#[command]
+#[aliases("reach")]
+async fn reachability(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let fumen_data = match args.trimmed().current() {
+        Some(data) => data,
+        None => {
+            msg.channel_id.say(&ctx.http, "Please pass a fumen with a piece placement").await?;
+            return Ok(())
+        }
+    };
+    let (fumen, options) = match extract_fumen(fumen_data).await {
+        Some(data) => data,
+        None => {
+            msg.channel_id.say(&ctx.http, "Invalid fumen").await?;
+            return Ok(())
+        }
+    };
+    let rs = match rotation::RotationSystem::from_options(options) {
+        Ok(rs) => rs,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let options = options.to_owned();
+    let page = match fumen.pages.iter().find(|p| p.piece.is_some()) {
+        Some(page) => page,
+        None => {
+            msg.channel_id.say(&ctx.http, "Fumen should have a page with a piece placement.").await?;
+            return Ok(())
+        }
+    };
+    let field = page.field;
+    let board = Board::new_with_state(
+        to_libtetris_field(&field), Default::default(), None, false, 0
+    );
+    let target = to_libtetris(page.piece.unwrap());
+    if board.obstructed(&target) {
+        msg.channel_id.say(&ctx.http, "The placement overlaps the field.").await?;
+        return Ok(())
+    }
+    let search = match reach::Search::new(rs, &board, target.kind.0) {
+        Some(search) => search,
+        None => {
+            msg.channel_id.say(&ctx.http, "The piece can't spawn on this field.").await?;
+            return Ok(())
+        }
+    };
+
+    let mut result = Fumen::default();
+    let content = match search.path_to(&target) {
+        Some(path) => {
+            let first = result.add_page();
+            first.field = field;
+            first.piece = Some(to_fumen(search.spawn()));
+            first.comment = Some("spawn".to_owned());
+            for &(step, piece) in &path {
+                let page = result.add_page();
+                page.field = field;
+                page.piece = Some(to_fumen(piece));
+                page.comment = Some(step.name().to_owned());
+            }
+            format!(
+                "Reachable from spawn under {} in {} moves: {}",
+                rs.name(), path.len(), reach::describe_path(&path)
+            )
+        }
+        None => {
+            let attempts = search.failed_kicks(&board, &target);
+            let mut content = format!("Not reachable from spawn under {}.", rs.name());
+            if attempts.is_empty() {
+                content += " No reachable rotation lists this spot in its kick table.";
+            }
+            for attempt in attempts.iter().take(3) {
+                let fits = attempt.tests.iter().position(|&(_, fits)| fits).unwrap();
+                content += &format!(
+                    "\n{} from {} tries test {} first, which fits, so test {} is never reached.",
+                    attempt.step.name(), describe_location(&attempt.from), fits + 1,
+                    attempt.tests.len()
+                );
+            }
+            if let Some(attempt) = attempts.first() {
+                let first = result.add_page();
+                first.field = field;
+                first.piece = Some(to_fumen(attempt.from));
+                for (i, &(piece, fits)) in attempt.tests.iter().enumerate() {
+                    let page = result.add_page();
+                    page.field = field;
+                    page.piece = Some(to_fumen(piece));
+                    page.comment = Some(format!(
+                        "test {}: {}", i + 1, if fits { "fits" } else { "blocked" }
+                    ));
+                }
+            } else {
+                let page = result.add_page();
+                page.field = field;
+                page.piece = Some(to_fumen(target));
+            }
+            content
+        }
+    };
+
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(result, &options)
+    ).await.unwrap()?;
+    msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+        data: gif.into(),
+        filename: "fumen.gif".into()
+    }], |f| f.content(content)).await?;
+    Ok(())
+}
+
+fn describe_location(piece: &libtetris::FallingPiece) -> String {
+    let rotation = match piece.kind.1 {
+        libtetris::RotationState::North => "north",
+        libtetris::RotationState::East => "east",
+        libtetris::RotationState::South => "south",
+        libtetris::RotationState::West => "west",
+    };
+    format!("{} at ({}, {})", rotation, piece.x, piece.y)
+}
+
+/// Plays two Cold Clear instances against each other, e.g.
+/// `-versus pieces=100 seed=42 p1=default p2=fast p2.tspin2=400`.
+#[command]
+async fn versus(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let options = args.rest();
+    let mut pieces = 100;
+    let mut seed = None;
+    let mut names = ["default".to_owned(), "default".to_owned()];
+    for caps in EXTRACT_OPTIONS.captures_iter(options) {
+        let value = caps.get(2).unwrap().as_str();
+        match caps.get(1).unwrap().as_str() {
+            "pieces" => if let Ok(v) = value.parse::<usize>() {
+                pieces = v.min(MAX_VERSUS_PIECES);
+            }
+            "seed" => seed = value.parse().ok(),
+            "p1" => names[0] = value.to_owned(),
+            "p2" => names[1] = value.to_owned(),
+            _ => {}
+        }
+    }
+    let evaluators = match (
+        evaluator::from_options("p1", options), evaluator::from_options("p2", options)
+    ) {
+        (Ok(first), Ok(second)) => [first, second],
+        (Err(e), _) | (_, Err(e)) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let seed = seed.unwrap_or_else(queue::random_seed);
+
+    let ticket = match acquire_job(ctx, msg.channel_id, msg.author.id).await? {
+        Some(ticket) => ticket,
+        None => return Ok(())
+    };
+    let mut progress = progress::Progress::start(
+        ctx, msg.channel_id, &format!("Running versus with seed={}, placed 0/{}", seed, pieces)
+    ).await?;
+
+    let mut game = versus::Game::new(seed, evaluators);
+    while game.pieces < pieces {
+        if ticket.is_cancelled() {
+            progress.fail(ctx, format!(
+                "Versus cancelled after {}/{} pieces.", game.pieces, pieces
+            )).await?;
+            return Ok(())
+        }
+        let running = game.step().await;
+        progress.update(ctx, format!(
+            "Running versus with seed={}, placed {}/{}", seed, game.pieces, pieces
+        )).await?;
+        if !running {
+            break
+        }
+    }
+    drop(ticket);
+
+    let names = [names[0].as_str(), names[1].as_str()];
+    let summary = format!("Seed {}. {}", seed, game.summary(names));
+    let speed = render_speed(options);
+    let frames = std::mem::take(&mut game.frames);
+    drop(game);
+    let gif = tokio::task::spawn_blocking(
+        move || versus::render(&frames, speed)
+    ).await.unwrap();
+    match gif {
+        Ok(gif) => {
+            progress.finish(ctx, gif, Some(summary)).await?;
+        }
+        Err(e) => progress.fail(ctx, format!("Failed to render the game: {}", e)).await?
+    }
+    Ok(())
+}
+
+const MAX_VERSUS_PIECES: usize = 300;
+
+/// Lets Cold Clear play from an empty board with a seeded 7-bag, e.g. `-demo pieces=50 seed=7`.
+/// Evaluator settings can be given like `-cc`'s, e.g. `eval=fast tspin2=400`.
+#[command]
+async fn demo(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let options = args.rest().to_owned();
+    let mut pieces = 50;
+    let mut seed = None;
+    for caps in EXTRACT_OPTIONS.captures_iter(&options) {
+        let value = caps.get(2).unwrap().as_str();
+        match caps.get(1).unwrap().as_str() {
+            "pieces" => if let Ok(v) = value.parse::<usize>() {
+                pieces = v.min(MAX_DEMO_PIECES);
+            }
+            "seed" => seed = value.parse().ok(),
+            _ => {}
+        }
+    }
+    let evaluator = match evaluator::from_options("", &options) {
+        Ok(evaluator) => evaluator,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let seed = seed.unwrap_or_else(queue::random_seed);
+
+    let ticket = match acquire_job(ctx, msg.channel_id, msg.author.id).await? {
+        Some(ticket) => ticket,
+        None => return Ok(())
+    };
+    let mut progress = progress::Progress::start(
+        ctx, msg.channel_id, &format!("Running Cold Clear, placed 0/{}", pieces)
+    ).await?;
+
+    let mut bag = queue::Bag::new(seed);
+    let mut board = Board::new();
+    for _ in 0..6 {
+        board.add_next_piece(bag.next());
+    }
+    let shadow = board.clone();
+    let cc = cold_clear::Interface::launch(board, cold_clear::Options {
+        pcloop: None,
+        ..Default::default()
+    }, evaluator.clone(), None);
+
+    let empty = [[fumen::CellColor::Empty; 10]; 23];
+    let settings = LineSettings {
+        count: pieces,
+        rs: rotation::RotationSystem::Srs,
+        evaluator: &evaluator,
+        garbage: &mut garbage::Schedule::none(),
+        next_piece: || Some(bag.next()),
+    };
+    let line = play_line(ctx, &ticket, &mut progress, &cc, shadow, empty, settings).await?;
+    let (mut fumen, stats, _) = match line {
+        Some(line) => line,
+        None => return Ok(())
+    };
+    drop(cc);
+    drop(ticket);
+
+    let summary = stats.summary();
+    let last_page = fumen.add_page();
+    last_page.comment = Some(summary.clone());
+    let summary = match fumen_link(&fumen) {
+        Some(link) => format!("Seed {}: {}\n<{}>", seed, summary, link),
+        None => format!("Seed {}: {}", seed, summary)
+    };
+
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(fumen, &options)
+    ).await.unwrap();
+    match gif {
+        Ok(gif) => {
+            progress.finish(ctx, gif, Some(summary)).await?;
+        }
+        Err(e) => progress.fail(ctx, format!("Failed to render the demo: {}", e)).await?
+    }
+    Ok(())
+}
+
+const MAX_DEMO_PIECES: usize = 200;
+
+/// Generates a garbage field for downstack practice, e.g. `-cheese rows=10 messiness=30 seed=5`.
+/// The reply includes the fumen with a queue comment so it can be passed to `-cc`.
+#[command]
+async fn cheese(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let options = args.rest().to_owned();
+    let mut settings = cheese::Cheese { rows: 8, messiness: 50, seed: queue::random_seed() };
+    for caps in EXTRACT_OPTIONS.captures_iter(&options) {
+        let value = caps.get(2).unwrap().as_str();
+        match caps.get(1).unwrap().as_str() {
+            "rows" => if let Ok(v) = value.parse::<usize>() {
+                settings.rows = v.min(cheese::MAX_ROWS);
+            }
+            "messiness" => if let Ok(v) = value.parse::<u32>() {
+                settings.messiness = v.min(100);
+            }
+            "seed" => if let Ok(v) = value.parse() {
+                settings.seed = v;
+            }
+            _ => {}
+        }
+    }
+
+    let fumen = settings.generate();
+    let content = format!(
+        "Seed {}, {} rows, {}% messiness:\n`-cc {}`",
+        settings.seed, settings.rows, settings.messiness, fumen.encode()
+    );
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(fumen, &options)
+    ).await.unwrap();
+    match gif {
+        Ok(gif) => {
+            msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+                data: gif.into(),
+                filename: "fumen.gif".into()
+            }], |f| f.content(content)).await?;
+        }
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, format!("Failed to render the map: {}", e)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Starts an interactive session on a fumen with a `#Q=` quiz comment, controlled with the
+/// reactions on the board, e.g. `-play v115@...#goal=pc`. `goal` is `pc`, a number of lines to
+/// clear, or `none` to play out the queue.
+#[command]
+async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (fumen, options) = match extract_fumen(args.rest()).await {
+        Some(data) => data,
+        None => {
+            msg.channel_id.say(&ctx.http, "Please pass a fumen with a quiz comment").await?;
+            return Ok(())
+        }
+    };
+    let rs = match rotation::RotationSystem::from_options(options) {
+        Ok(rs) => rs,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let mut goal = play::Goal::Survive;
+    for caps in EXTRACT_OPTIONS.captures_iter(options) {
+        let value = caps.get(2).unwrap().as_str();
+        if caps.get(1).unwrap().as_str() == "goal" {
+            goal = match play::Goal::parse(value) {
+                Some(goal) => goal,
+                None => {
+                    let message = format!(
+                        "Unknown goal `{}`. Use `pc`, a number of lines or `none`.", value
+                    );
+                    msg.channel_id.say(&ctx.http, message).await?;
+                    return Ok(())
+                }
+            };
+        }
+    }
+    let page = &fumen.pages[0];
+    let (hold, current, pattern) = match parse_quiz(page) {
+        Ok(quiz) => quiz,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let field = to_libtetris_field(&page.field);
+    if field.iter().any(|&r| r == [true; 10]) {
+        msg.channel_id.say(&ctx.http, "Fumen contains a complete row.").await?;
+        return Ok(())
+    }
+    let next = match pattern.enumerate(1) {
+        Some(mut queues) => queues.remove(0),
+        None => pattern.sample(&mut queue::Rng::new(queue::random_seed()))
+    };
+    let mut board = Board::new_with_state(field, Default::default(), hold, false, 0);
+    for p in next {
+        board.add_next_piece(p);
+    }
+
+    let mut session = play::Session::new(msg.author.id, board, &page.field, current, rs, goal);
+    let message = msg.channel_id.say(&ctx.http, session.status()).await?;
+    post_play_board(ctx, msg.channel_id, &mut session).await?;
+    if session.is_over() {
+        return Ok(())
+    }
+    let mut sessions = play::SESSIONS.lock().await;
+    // A session that is locked is handling an input, so it isn't idle.
+    sessions.retain(|_, s| s.try_lock().map_or(true, |s| {
+        s.user != msg.author.id && s.last_input.elapsed() < play::IDLE_TIMEOUT
+    }));
+    sessions.insert(message.id, std::sync::Arc::new(tokio::sync::Mutex::new(session)));
+    drop(sessions);
+
+    for &(emoji, action) in play::CONTROLS {
+        if action == play::Action::Input(finesse::Input::Flip) && !rs.has_180() {
+            continue
+        }
+        message.react(ctx, ReactionType::Unicode(emoji.to_owned())).await?;
+    }
+    Ok(())
+}
+
+/// Registers the slash commands once the bot has connected.
+pub async fn register_slash_commands(ctx: &Context) {
+    slash::register(ctx).await;
+}
+
+/// Handles slash commands and the speed buttons under renders.
+pub async fn interaction(ctx: &Context, interaction: serenity::model::interactions::Interaction) {
+    slash::interaction(ctx, interaction).await;
+}
+
+/// Posts the daily puzzle to the configured channels. Runs for the lifetime of the bot.
+pub async fn daily_puzzles(http: std::sync::Arc<serenity::http::Http>) {
+    daily::run(http).await
+}
+
+/// Applies a reaction on a `-play` board from the player to their session.
+pub async fn play_reaction(ctx: &Context, reaction: &Reaction) {
+    let action = match &reaction.emoji {
+        ReactionType::Unicode(emoji) => match play::action(emoji) {
+            Some(action) => action,
+            None => return
+        },
+        _ => return
+    };
+    let session = match play::SESSIONS.lock().await.get(&reaction.message_id) {
+        Some(session) => session.clone(),
+        None => return
+    };
+    // The session's lock is held until the updates are sent so that quick inputs are shown in
+    // order. Other sessions don't wait for it.
+    let mut session = session.lock().await;
+    if Some(session.user) != reaction.user_id || !session.apply(action) {
+        return
+    }
+    let text = session.status();
+    let _ = reaction.channel_id.edit_message(
+        &ctx.http, reaction.message_id, |m| m.content(text)
+    ).await;
+    if let Err(e) = post_play_board(ctx, reaction.channel_id, &mut session).await {
+        println!("Failed to show the board: {}", e);
+    }
+    if session.is_over() {
+        play::SESSIONS.lock().await.remove(&reaction.message_id);
+    }
+}
+
+/// Posts the board of a `-play` session and removes the previous one. Discord doesn't let bots
+/// replace the image on a message, so each update is a new message below the controls.
+async fn post_play_board(
+    ctx: &Context, channel: ChannelId, session: &mut play::Session
+) -> CommandResult {
+    let gif = session.render()?;
+    let board = channel.send_files(&ctx.http, vec![AttachmentType::Bytes {
+        data: gif.into(),
+        filename: "board.gif".into()
+    }], |m| m).await?;
+    if let Some(previous) = session.board_message.replace(board.id) {
+        channel.delete_message(&ctx.http, previous).await?;
+    }
+    Ok(())
+}
 
 #[group]
-#[commands(cold_clear_analysis)]
-pub struct Tetris;
+#[only_in(guilds)]
+#[commands(daily, answer, leaderboard)]
+pub struct DailyPuzzle;
 
+/// Shows today's puzzle.
 #[command]
-#[aliases("cc")]
-async fn cold_clear_analysis(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
-    let fumen_data = match args.trimmed().current() {
-        Some(data) => data,
+async fn daily(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let typing = msg.channel_id.start_typing(&ctx.http)?;
+    let puzzle = daily::puzzle().await;
+    let fumen = puzzle.fumen.clone();
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(fumen, "")
+    ).await.unwrap()?;
+    msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+        data: gif.into(),
+        filename: "puzzle.gif".into()
+    }], |f| f.content(puzzle.announcement())).await?;
+    let _ = typing.stop();
+    Ok(())
+}
+
+/// Scores an answer to today's puzzle, given as a fumen that places the queue from the puzzle's
+/// field. The answer is deleted so that it doesn't spoil the puzzle for others.
+#[command]
+#[aliases("submit")]
+async fn answer(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let _ = msg.delete(ctx).await;
+    let answer = match extract_fumen(args.rest()).await {
+        Some((fumen, _)) => fumen,
         None => {
-            msg.channel_id.say(&ctx.http, "Please pass a fumen to analyse").await?;
+            msg.channel_id.say(&ctx.http, "Please pass your answer as a fumen").await?;
             return Ok(())
         }
     };
-    let (fumen, options) = match extract_fumen(fumen_data).await {
+    let puzzle = daily::puzzle().await;
+    if !daily::same_start(&puzzle, &answer) {
+        msg.channel_id.say(&ctx.http, "The answer doesn't start from today's puzzle.").await?;
+        return Ok(())
+    }
+    let score = match puzzle.score(&daily::placements(&answer)) {
+        Ok(score) => score,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, format!("{}: {}", msg.author.mention(), e)).await?;
+            return Ok(())
+        }
+    };
+    puzzle.target().await;
+    let mut text = format!("{}: {}.", msg.author.mention(), puzzle.describe(&score));
+    match puzzle.solved_by(&score) {
+        Some(true) => {
+            let streak = daily::record_solve(msg.guild_id.unwrap(), msg.author.id, puzzle.day)
+                .await;
+            text += &format!(" Solved! Streak: {}.", streak);
+        }
+        Some(false) => text += " Not quite, try again!",
+        None => text += " Cold Clear couldn't score today's puzzle, so answers aren't scored.",
+    }
+    msg.channel_id.say(&ctx.http, text).await?;
+    Ok(())
+}
+
+/// Shows this server's daily puzzle leaderboard.
+#[command]
+async fn leaderboard(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
+    let text = daily::leaderboard(msg.guild_id.unwrap()).await;
+    msg.channel_id.send_message(&ctx.http, |m| {
+        m.content(text).allowed_mentions(|a| a.empty_parse())
+    }).await?;
+    Ok(())
+}
+
+#[group]
+#[only_in(guilds)]
+#[commands(save_fumen, saved, recall, rename_fumen, unsave)]
+pub struct FumenLibrary;
+
+/// Saves a fumen for this server under a name with optional tags, e.g.
+/// `-save tsd-basics v115@... #tspin #beginner`.
+#[command]
+#[aliases("save")]
+async fn save_fumen(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = msg.guild_id.unwrap();
+    let name = match args.single::<String>() {
+        Ok(name) => name,
+        Err(_) => {
+            msg.channel_id.say(&ctx.http, "Please pass a name and a fumen to save").await?;
+            return Ok(())
+        }
+    };
+    let rest = args.rest();
+    let (fumen, options) = match extract_fumen(rest).await {
         Some(data) => data,
         None => {
             msg.channel_id.say(&ctx.http, "Invalid fumen").await?;
             return Ok(())
         }
     };
-    let options = options.to_owned();
-    if fumen.pages.len() != 1 {
-        msg.channel_id.say(&ctx.http, "Fumen should have 1 page and a queue comment.").await?;
+    let tags = rest.split_whitespace()
+        .filter_map(|word| word.strip_prefix('#'))
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let saved = library::SavedFumen {
+        data: format!("{}{}", fumen.encode(), options),
+        tags,
+        author: msg.author.id,
+    };
+    match library::save(guild, &name, saved).await {
+        Ok(()) => msg.channel_id.say(&ctx.http, format!("Saved `{}`.", name)).await?,
+        Err(e) => msg.channel_id.say(&ctx.http, e).await?
+    };
+    Ok(())
+}
+
+/// Lists the fumens saved on this server. Words filter by name and `#tag`s by tag, e.g.
+/// `-saved tsd #tspin`.
+#[command]
+#[aliases("fumens")]
+async fn saved(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let found = library::search(msg.guild_id.unwrap(), args.rest()).await;
+    if found.is_empty() {
+        msg.channel_id.say(&ctx.http, "No saved fumens found.").await?;
         return Ok(())
     }
-    let page = &fumen.pages[0];
-    lazy_static! {
-        static ref QUEUE_SELECTOR: Regex = Regex::new(
-            r"^#Q=\[([IOTJLSZ]?)\]\(([IOTJLSZ])\)([IOTJLSZ]*)$"
-        ).unwrap();
+    let mut text = String::new();
+    for (name, tags) in found {
+        let line = if tags.is_empty() {
+            format!("`{}`\n", name)
+        } else {
+            format!("`{}` #{}\n", name, tags.join(" #"))
+        };
+        if text.len() + line.len() > 1900 {
+            text += "...";
+            break
+        }
+        text += &line;
     }
-    if let Some(caps) = page.comment.as_ref().and_then(|c| QUEUE_SELECTOR.captures(c)) {
-        let hold = caps.get(1).unwrap();
-        let hold = hold.as_str().chars().next().and_then(from_char);
-        let current = caps.get(2).unwrap();
-        let next = caps.get(3).unwrap();
-        let mut field = [[false; 10]; 40];
-        for y in 0..23 {
-            for x in 0..10 {
-                field[y][x] = page.field[y][x] != fumen::CellColor::Empty;
-            }
+    msg.channel_id.say(&ctx.http, text).await?;
+    Ok(())
+}
+
+/// Renders a saved fumen, e.g. `-recall tsd-basics`.
+#[command]
+#[aliases("r")]
+async fn recall(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let name = args.rest().trim();
+    let saved = match library::get(msg.guild_id.unwrap(), name).await {
+        Some(saved) => saved,
+        None => {
+            msg.channel_id.say(&ctx.http, "There is no fumen saved under that name.").await?;
+            return Ok(())
         }
-        if field.iter().any(|&r| r == [true; 10]) {
-            msg.channel_id.say(&ctx.http, "Fumen contains a complete row.").await?;
+    };
+    let (fumen, options) = match extract_fumen(&saved.data).await {
+        Some(data) => data,
+        None => {
+            msg.channel_id.say(&ctx.http, "The saved fumen could not be read.").await?;
             return Ok(())
         }
-        let mut board = Board::new_with_state(field, Default::default(), hold, false, 0);
-        board.add_next_piece(current.as_str().chars().next().and_then(from_char).unwrap());
-        for c in next.as_str().chars() {
-            board.add_next_piece(from_char(c).unwrap());
+    };
+    let options = options.to_owned();
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(fumen, &options)
+    ).await.unwrap()?;
+    let content = if saved.data.len() > 1800 {
+        format!("`{}`", name)
+    } else {
+        format!("`{}`: {}", name, saved.data)
+    };
+    msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+        data: gif.into(),
+        filename: "fumen.gif".into()
+    }], |f| f.content(content)).await?;
+    Ok(())
+}
+
+/// Renames a fumen you saved, e.g. `-rename tsd-basics tsd-intro`.
+#[command]
+#[aliases("rename")]
+async fn rename_fumen(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (from, to) = match (args.single::<String>(), args.single::<String>()) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => {
+            msg.channel_id.say(&ctx.http, "Please pass the current and new names").await?;
+            return Ok(())
+        }
+    };
+    match library::rename(msg.guild_id.unwrap(), msg.author.id, &from, &to).await {
+        Ok(()) => {
+            msg.channel_id.say(&ctx.http, format!("Renamed `{}` to `{}`.", from, to)).await?
         }
+        Err(e) => msg.channel_id.say(&ctx.http, e).await?
+    };
+    Ok(())
+}
 
-        let count = (board.next_queue().count() + (hold.is_some() as usize) - 1).min(40);
+/// Deletes a fumen you saved, e.g. `-unsave tsd-basics`.
+#[command]
+async fn unsave(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let name = args.rest().trim();
+    match library::delete(msg.guild_id.unwrap(), msg.author.id, name).await {
+        Ok(()) => msg.channel_id.say(&ctx.http, format!("Deleted `{}`.", name)).await?,
+        Err(e) => msg.channel_id.say(&ctx.http, e).await?
+    };
+    Ok(())
+}
 
-        msg.channel_id.broadcast_typing(&ctx.http).await?;
+/// Renders a setup from the bundled library, e.g. `-setup dt cannon`.
+#[command]
+async fn setup(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let name = args.rest().trim();
+    let setup = match setups::find(name) {
+        Some(setup) => setup,
+        None => {
+            let message = format!("Known setups: {}", setups::names().join(", "));
+            msg.channel_id.say(&ctx.http, message).await?;
+            return Ok(())
+        }
+    };
+    let fumen = setup.fumen();
+    let content = format!("{}: {}", setup.name, fumen.encode());
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(fumen, "")
+    ).await.unwrap()?;
+    msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+        data: gif.into(),
+        filename: "fumen.gif".into()
+    }], |f| f.content(content)).await?;
+    Ok(())
+}
 
-        println!("Running Cold Clear...");
+/// Renders two fumens side by side with their differences outlined, e.g.
+/// `-compare v115@... v115@...#speed=2`. Render options are taken from the first fumen.
+#[command]
+async fn compare(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (first, second) = match (args.single::<String>(), args.single::<String>()) {
+        (Ok(first), Ok(second)) => (first, second),
+        _ => {
+            msg.channel_id.say(&ctx.http, "Please pass two fumens to compare").await?;
+            return Ok(())
+        }
+    };
+    let (first, options) = match extract_fumen(&first).await {
+        Some((fumen, options)) => (fumen, options.to_owned()),
+        None => {
+            msg.channel_id.say(&ctx.http, "The first fumen is invalid").await?;
+            return Ok(())
+        }
+    };
+    let second = match extract_fumen(&second).await {
+        Some((fumen, _)) => fumen,
+        None => {
+            msg.channel_id.say(&ctx.http, "The second fumen is invalid").await?;
+            return Ok(())
+        }
+    };
+    let gif = tokio::task::spawn_blocking(
+        move || compare::render([&first, &second], render_speed(&options))
+    ).await.unwrap()?;
+    msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+        data: gif.into(),
+        filename: "compare.gif".into()
+    }], |f| f).await?;
+    Ok(())
+}
 
-        let cc = cold_clear::Interface::launch(board, cold_clear::Options {
-            speculate: false,
-            pcloop: None,
-            ..Default::default()
-        }, cold_clear::evaluation::Standard::default(), None);
-
-        let mut fumen = Fumen::default();
-        let first_page = fumen.add_page();
-        first_page.field = page.field;
-
-        for _ in 0..count {
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-            cc.suggest_next_move(0);
-            tokio::task::yield_now().await;
-            if let Some((mv, info)) = cc.block_next_move() {
-                cc.play_next_move(mv.expected_location);
-                let page = fumen.add_page();
-                page.piece = Some(to_fumen(mv.expected_location));
-                if let cold_clear::Info::Normal(info) = info {
-                    page.comment = Some(format!("{}n, {}d", info.nodes, info.depth));
-                }
-            } else {
-                break;
+/// Lists the Standard evaluator's terms for a page of a fumen, with the piece on the page locked,
+/// e.g. `-eval v115@...#page=3`. `vs=5` puts another page's breakdown alongside, and evaluator
+/// settings can be given like `-cc`'s.
+#[command]
+#[aliases("eval")]
+async fn evaluate(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (fumen, options) = match extract_fumen(args.rest()).await {
+        Some(data) => data,
+        None => {
+            msg.channel_id.say(&ctx.http, "Please pass a fumen to evaluate").await?;
+            return Ok(())
+        }
+    };
+    let evaluator = match evaluator::from_options("", options) {
+        Ok(evaluator) => evaluator,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let mut pages = vec![1];
+    for caps in EXTRACT_OPTIONS.captures_iter(options) {
+        let value = caps.get(2).unwrap().as_str();
+        match caps.get(1).unwrap().as_str() {
+            "page" => if let Ok(v) = value.parse() {
+                pages[0] = v;
             }
+            "vs" => if let Ok(v) = value.parse() {
+                pages.push(v);
+            }
+            _ => {}
         }
+    }
+    if pages.len() > 2 {
+        msg.channel_id.say(&ctx.http, "Only one `vs=` page can be compared at a time.").await?;
+        return Ok(())
+    }
+    if let Some(&page) = pages.iter().find(|&&p| p == 0 || p > fumen.pages.len()) {
+        let message = format!("There is no page {}; the fumen has {}.", page, fumen.pages.len());
+        msg.channel_id.say(&ctx.http, message).await?;
+        return Ok(())
+    }
 
-        let gif = tokio::task::spawn_blocking(
-            move || render_fumen(fumen, &options)
-        ).await.unwrap().unwrap();
-        msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
-            data: gif.into(),
-            filename: "fumen.gif".into()
-        }], |f| f).await.unwrap();
+    let breakdowns: Vec<_> = pages.iter().map(|&p| {
+        let page = &fumen.pages[p - 1];
+        let mut board = Board::new();
+        board.set_field(to_libtetris_field(&page.field));
+        if let Some(piece) = page.piece {
+            board.lock_piece(to_libtetris(piece));
+        }
+        breakdown::breakdown(&evaluator, &board)
+    }).collect();
+
+    let mut table = format!("{:<18}", "term");
+    for &p in &pages {
+        table += &format!("{:>22}", format!("page {}", p));
+    }
+    table += "\n";
+    for (i, term) in breakdowns[0].iter().enumerate() {
+        table += &format!("{:<18}", term.name);
+        for terms in &breakdowns {
+            let term = &terms[i];
+            table += &format!(
+                "{:>22}", format!("{} x {} = {}", term.amount, term.weight, term.contribution())
+            );
+        }
+        table += "\n";
+    }
+    table += &format!("{:<18}", "total");
+    for terms in &breakdowns {
+        table += &format!("{:>22}", terms.iter().map(|t| t.contribution()).sum::<i32>());
+    }
+    msg.channel_id.say(&ctx.http, format!(
+        "```\n{}\n```T-slots are approximated rather than found the way Cold Clear finds them, so \
+        the total can differ from Cold Clear's own evaluation.",
+        table
+    )).await?;
+    Ok(())
+}
+
+/// Converts the first page of a fumen, and its `#Q=` queue if it has one, to a TETR.IO custom game
+/// map string.
+#[command]
+#[aliases("totetrio")]
+async fn to_tetrio(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let fumen = match extract_fumen(args.rest()).await {
+        Some((fumen, _)) => fumen,
+        None => {
+            msg.channel_id.say(&ctx.http, "Please pass a fumen to convert").await?;
+            return Ok(())
+        }
+    };
+    let page = &fumen.pages[0];
+    let mut map = map::Map { field: page.field, queue: vec![], hold: None };
+    if page.comment.is_some() {
+        if let Ok((hold, current, pattern)) = parse_quiz(page) {
+            map.hold = hold;
+            map.queue.push(current);
+            map.queue.extend(match pattern.enumerate(1) {
+                Some(mut queues) => queues.remove(0),
+                None => pattern.sample(&mut queue::Rng::new(queue::random_seed()))
+            });
+        }
+    }
+    msg.channel_id.say(&ctx.http, format!("`{}`", map.encode())).await?;
+    Ok(())
+}
+
+/// Converts a TETR.IO custom game map string (`board?bag?hold`) to a fumen with a `#Q=` queue.
+#[command]
+#[aliases("fromtetrio")]
+async fn from_tetrio(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let map = match map::Map::parse(args.rest()) {
+        Ok(map) => map,
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, e).await?;
+            return Ok(())
+        }
+    };
+    let fumen = map.fumen();
+    let content = fumen.encode();
+    let gif = tokio::task::spawn_blocking(
+        move || render_fumen(fumen, "")
+    ).await.unwrap()?;
+    msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+        data: gif.into(),
+        filename: "fumen.gif".into()
+    }], |f| f.content(content)).await?;
+    Ok(())
+}
+
+/// Writes a fumen's fields as Hard Drop wiki playfield diagrams.
+#[command]
+#[aliases("wiki")]
+async fn to_wiki(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    send_diagram(ctx, msg, args.rest(), diagram::to_wiki).await
+}
+
+/// Writes a fumen's fields as four.lol text grids.
+#[command]
+#[aliases("grid")]
+async fn to_grid(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    send_diagram(ctx, msg, args.rest(), diagram::to_grid).await
+}
 
-        Ok(())
+/// Replies with `convert`'s text in a code block, or as a file if it's too long for a message.
+async fn send_diagram(
+    ctx: &Context, msg: &Message, text: &str, convert: fn(&Fumen) -> String
+) -> CommandResult {
+    let fumen = match extract_fumen(text).await {
+        Some((fumen, _)) => fumen,
+        None => {
+            msg.channel_id.say(&ctx.http, "Please pass a fumen to convert").await?;
+            return Ok(())
+        }
+    };
+    let diagram = convert(&fumen);
+    if diagram.len() > 1900 {
+        msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+            data: diagram.into_bytes().into(),
+            filename: "diagram.txt".into()
+        }], |f| f).await?;
     } else {
-        msg.channel_id.say(&ctx.http, "Fumen should have 1 page and a queue comment.").await?;
-        Ok(())
+        msg.channel_id.say(&ctx.http, format!("```\n{}```", diagram)).await?;
     }
+    Ok(())
 }
 
 #[hook]
@@ -113,24 +1558,152 @@ pub async fn normal_message(ctx: &Context, msg: &Message) {
     if msg.content.starts_with('-') {
         return
     }
-    if let Some((fumen, options)) = extract_fumen(&msg.content).await {
-        let options = options.to_owned();
-        let gif = tokio::task::spawn_blocking(
-            move || render_fumen(fumen, &options)
-        ).await.unwrap().unwrap();
-        msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
-            data: gif.into(),
+    for attachment in &msg.attachments {
+        if attachment.filename.ends_with(".ttr") || attachment.filename.ends_with(".ttrm") {
+            if let Err(e) = render_replay(ctx, msg, attachment).await {
+                println!("Failed to render replay: {}", e);
+            }
+        }
+    }
+    if let Some(reply) = send_render(ctx, msg.channel_id, &msg.content, None).await {
+        replies::remember(msg.id, reply).await;
+    }
+}
+
+/// Renders the fumen in `text` into `channel`, as a reply to `reply_to` if given, returning the
+/// message it was posted as.
+async fn send_render(
+    ctx: &Context, channel: ChannelId, text: &str, reply_to: Option<MessageId>
+) -> Option<MessageId> {
+    let rendered = render_text(text, None).await?;
+    let reply = channel.send_files(&ctx.http, vec![rendered.attachment()], |f| {
+        f.components(slash::speed_buttons);
+        if let Some(source) = reply_to {
+            f.reference_message((channel, source)).allowed_mentions(|m| m.replied_user(false));
+        }
+        match rendered.content {
+            Some(content) => f.content(content),
+            None => f
+        }
+    }).await.unwrap();
+    slash::remember_render(reply.id, text).await;
+    Some(reply.id)
+}
+
+/// Replaces the render of an edited message with one of its new text, or removes it if the text
+/// no longer has a fumen. Discord doesn't let bots swap a message's attachment, so the new render
+/// is posted as a reply to the edited message, keeping the two linked, and the old one is deleted.
+pub async fn source_edited(ctx: &Context, channel: ChannelId, source: MessageId, text: &str) {
+    let reply = match replies::forget(source).await {
+        Some(reply) => reply,
+        None => return
+    };
+    if !text.starts_with('-') {
+        if let Some(new_reply) = send_render(ctx, channel, text, Some(source)).await {
+            replies::remember(source, new_reply).await;
+        }
+    }
+    if let Err(e) = channel.delete_message(&ctx.http, reply).await {
+        println!("Failed to delete outdated render: {}", e);
+    }
+}
+
+/// Removes the render of a deleted message.
+pub async fn source_deleted(ctx: &Context, channel: ChannelId, source: MessageId) {
+    if let Some(reply) = replies::forget(source).await {
+        if let Err(e) = channel.delete_message(&ctx.http, reply).await {
+            println!("Failed to delete orphaned render: {}", e);
+        }
+    }
+}
+
+/// A fumen rendered from the text of a message or slash command.
+pub struct Rendered {
+    gif: Vec<u8>,
+    content: Option<String>,
+}
+
+impl Rendered {
+    fn attachment(&self) -> AttachmentType<'static> {
+        AttachmentType::Bytes {
+            data: self.gif.clone().into(),
             filename: "fumen.gif".into()
-        }], |f| f).await.unwrap();
+        }
+    }
+}
+
+/// Renders the fumen found in `text`, naming the setup it shows if it's a known one. `speed`
+/// overrides the speed in the fumen's options.
+pub async fn render_text(text: &str, speed: Option<f64>) -> Option<Rendered> {
+    let (fumen, options) = extract_fumen(text).await?;
+    let options = match speed {
+        Some(speed) => format!("{}&speed={}", options, speed),
+        None => options.to_owned()
+    };
+    let setup = fumen.pages.iter().find_map(|page| setups::recognize(&page.field));
+    let key = cache::key("render", &fumen, &options);
+    let gif = match cache::CACHE.get(&key).await {
+        Some(gif) => gif,
+        None => {
+            let gif = tokio::task::spawn_blocking(
+                move || render_fumen(fumen, &options)
+            ).await.unwrap().unwrap();
+            cache::CACHE.insert(&key, gif.clone()).await;
+            gif
+        }
+    };
+    Some(Rendered { gif, content: setup.map(|setup| format!("Looks like: {}", setup.name)) })
+}
+
+/// Replays the first game of an attached TETR.IO replay and renders its placements.
+async fn render_replay(
+    ctx: &Context, msg: &Message, attachment: &serenity::model::channel::Attachment
+) -> serenity::Result<()> {
+    if attachment.size > MAX_REPLAY_SIZE {
+        msg.channel_id.say(&ctx.http, "That replay is too large to read.").await?;
+        return Ok(())
+    }
+    let data = attachment.download().await?;
+    let result = tokio::task::spawn_blocking(move || {
+        let replays = tetrio::parse(&data)?;
+        let replay = &replays[0];
+        let fumen = tetrio::simulate(replay);
+        let pieces = fumen.pages.iter().filter(|p| p.piece.is_some()).count();
+        let mut summary = format!("{}: {} pieces", replay.player, pieces);
+        if replays.len() > 1 {
+            summary += &format!(" (first of {} games in the replay)", replays.len());
+        }
+        if let Some(link) = fumen_link(&fumen) {
+            summary += &format!("\n<{}>", link);
+        }
+        let gif = render_fumen(fumen, "").map_err(|e| e.to_string())?;
+        Ok::<_, String>((gif, summary))
+    }).await.unwrap();
+    match result {
+        Ok((gif, summary)) => {
+            msg.channel_id.send_files(&ctx.http, vec![AttachmentType::Bytes {
+                data: gif.into(),
+                filename: "replay.gif".into()
+            }], |f| f.content(summary)).await?;
+        }
+        Err(e) => {
+            msg.channel_id.say(&ctx.http, format!("Couldn't read the replay: {}", e)).await?;
+        }
     }
+    Ok(())
 }
 
+const MAX_REPLAY_SIZE: u64 = 8 * 1024 * 1024;
+
 async fn extract_fumen(text: &str) -> Option<(Fumen, &str)> {
     lazy_static! {
         static ref FUMEN_DATA: Regex = Regex::new(r"(v115@[a-zA-Z0-9+/?]+)(#[^ ]+)?").unwrap();
         static ref TINYURL: Regex = Regex::new(
             r"((?:https?://)?tinyurl.com/[0-9a-zA-Z\-]+)(#[^ ]+)?"
         ).unwrap();
+        static ref JSTRIS_REPLAY: Regex = Regex::new(
+            r"(?:https?://)?jstris\.jezevec10\.com/replay/(\d+)(#[^ ]+)?"
+        ).unwrap();
     }
 
     if let Some(caps) = FUMEN_DATA.captures(text) {
@@ -161,32 +1734,78 @@ async fn extract_fumen(text: &str) -> Option<(Fumen, &str)> {
                     e
                 }).ok()
             ).map(|f| (f, caps.get(2).map(|m| m.as_str()).unwrap_or("")))
+    } else if let Some(caps) = JSTRIS_REPLAY.captures(text) {
+        let id = caps.get(1).unwrap().as_str();
+        println!("Found jstris replay {}", id);
+        let data = jstris::fetch(&jstris::HttpResolver, id).await?;
+        decode_jstris(&data).map(|f| (f, caps.get(2).map(|m| m.as_str()).unwrap_or("")))
+    } else if let Some(data) = jstris::find_data(text) {
+        println!("Found jstris replay data");
+        decode_jstris(&data).map(|f| (f, ""))
+    } else if let Some(fumen) = diagram::parse_wiki(text) {
+        println!("Found wiki diagram");
+        Some((fumen, ""))
+    } else if let Some(fumen) = diagram::parse_grid(text) {
+        println!("Found text grid");
+        Some((fumen, ""))
     } else {
         None
     }
 }
 
+fn decode_jstris(data: &jstris::ReplayData) -> Option<Fumen> {
+    jstris::decode(data).map_err(|e| {
+        println!("Failed to decode jstris replay: {}", e);
+        e
+    }).ok()
+}
+
 fn render_fumen(fumen: Fumen, options: &str) -> Result<Vec<u8>, gif::EncodingError> {
-    lazy_static! {
-        static ref EXTRACT_OPTIONS: Regex = Regex::new(
-            r"([\w._]+)=([\w._]+)"
-        ).unwrap();
+    render_fumen_with_meter(fumen, options, None)
+}
+
+/// Renders like `render_fumen`, with the lines in `meter` for each page shown as incoming garbage
+/// to the left of the field.
+fn render_fumen_with_meter(
+    fumen: Fumen, options: &str, meter: Option<&[u32]>
+) -> Result<Vec<u8>, gif::EncodingError> {
+    let speed = render_speed(options);
+    let palette = render_theme(options);
+
+    let has_garbage_row = fumen.pages.iter()
+        .any(|p| p.garbage_row != [fumen::CellColor::Empty; 10]);
+    let mut height = has_garbage_row as usize + fumen.pages.iter()
+        .map(|p| render::field_rows(&p.field, p.piece))
+        .max().unwrap();
+    let mut left = 0;
+    if meter.is_some() {
+        // Leave room for a few lines in the meter above a low field.
+        height = height.max(4);
+        left = garbage::METER_WIDTH;
+    }
+    let width = left + render::BLOCK_SIZE * 10;
+    let mut gif_data = vec![];
+    let mut writer = render::themed_encoder(
+        &mut gif_data, width, render::pixel_height(height, has_garbage_row), palette
+    )?;
+
+    for (i, page) in fumen.pages.into_iter().enumerate() {
+        let mut canvas = render::Canvas::new(width, height, has_garbage_row);
+        canvas.draw_page(left, &page);
+        if let Some(meter) = meter {
+            garbage::draw_meter(&mut canvas, 0, meter.get(i).copied().unwrap_or(0));
+        }
+        writer.write_frame(&canvas.into_frame(speed))?
     }
 
-    const GLOBAL_PALETTE: &'static [u8] = &[
-        0x40, 0x40, 0x40,
-        0x00, 0xFF, 0xFF,
-        0xFF, 0x80, 0x00,
-        0xFF, 0xFF, 0x00,
-        0xFF, 0x00, 0x00,
-        0x80, 0x00, 0xFF,
-        0x00, 0x20, 0xFF,
-        0x00, 0xFF, 0x00,
-        0x80, 0x80, 0x80,
-        0x10, 0x10, 0x10
-    ];
-    const BLOCK_SIZE: usize = 24;
+    drop(writer);
+
+    println!("gif is {} bytes large", gif_data.len());
+
+    Ok(gif_data)
+}
 
+fn render_speed(options: &str) -> f64 {
     let mut speed = 1.0f64;
     for caps in EXTRACT_OPTIONS.captures_iter(options) {
         let key = caps.get(1).unwrap().as_str();
@@ -198,79 +1817,83 @@ fn render_fumen(fumen: Fumen, options: &str) -> Result<Vec<u8>, gif::EncodingErr
             _ => {}
         }
     }
+    speed
+}
 
-    let has_garbage_row = fumen.pages.iter()
-        .any(|p| p.garbage_row != [fumen::CellColor::Empty; 10]);
-    let height = has_garbage_row as usize + fumen.pages.iter()
-        .map(|p| p.field.iter()
-            .enumerate()
-            .rfind(|(_,&r)| r != [fumen::CellColor::Empty; 10])
-            .map(|(i,_)| i)
-            .unwrap_or(0)
-            .max(p.piece
-                .map(to_libtetris)
-                .and_then(|p| p.cells().iter()
-                    .map(|&(_,y)| y as usize)
-                    .max())
-                .unwrap_or(0)
-            )
-        ).max().unwrap() + 1;
-    let gif_height = height*BLOCK_SIZE + 2*(has_garbage_row as usize);
-    let mut gif_data = vec![];
-    let mut writer = gif::Encoder::new(
-        &mut gif_data, BLOCK_SIZE as u16 * 10, gif_height as u16, GLOBAL_PALETTE
-    )?;
-    writer.set_repeat(gif::Repeat::Infinite)?;
+/// The palette of the last known `theme=` in `options`.
+fn render_theme(options: &str) -> &'static [u8] {
+    EXTRACT_OPTIONS.captures_iter(options)
+        .filter(|caps| caps.get(1).unwrap().as_str() == "theme")
+        .filter_map(|caps| render::theme(caps.get(2).unwrap().as_str()))
+        .last()
+        .unwrap_or(render::GLOBAL_PALETTE)
+}
 
-    for page in fumen.pages {
-        let mut buf = vec![0; BLOCK_SIZE as usize * 10 * gif_height];
-        let mut fill_tile = |x: usize, y: i32, color: fumen::CellColor| {
-            let tp = BLOCK_SIZE*x + BLOCK_SIZE*BLOCK_SIZE*10*(
-                (height as i32 - y - 1 - has_garbage_row as i32) as usize
-            );
-            for iy in 0..BLOCK_SIZE {
-                for ix in 0..BLOCK_SIZE {
-                    let i = tp + iy*10*BLOCK_SIZE + ix;
-                    if has_garbage_row && y == -1 {
-                        if iy < 2 {
-                            buf[i] = 9;
-                        }
-                        buf[i + 2*10*BLOCK_SIZE] = color as u8;
-                    } else {
-                        buf[i] = color as u8;
-                    }
-                }
-            }
-        };
-        for y in 0..height - has_garbage_row as usize {
-            for x in 0..10 {
-                fill_tile(x, y as i32, page.field[y][x]);
-            }
-        }
-        if has_garbage_row {
-            for x in 0..10 {
-                fill_tile(x, -1, page.garbage_row[x]);
-            }
+/// The colour fumen draws `piece` in.
+pub(crate) fn piece_color(piece: libtetris::Piece) -> fumen::CellColor {
+    match piece {
+        libtetris::Piece::I => fumen::CellColor::I,
+        libtetris::Piece::O => fumen::CellColor::O,
+        libtetris::Piece::T => fumen::CellColor::T,
+        libtetris::Piece::L => fumen::CellColor::L,
+        libtetris::Piece::J => fumen::CellColor::J,
+        libtetris::Piece::S => fumen::CellColor::S,
+        libtetris::Piece::Z => fumen::CellColor::Z,
+    }
+}
+
+/// Colours `piece` into `colors` and removes the rows it cleared, mirroring `Board::lock_piece`.
+fn lock_colors(
+    colors: &mut [[fumen::CellColor; 10]; 40], piece: libtetris::FallingPiece, cleared: &[i32]
+) {
+    let color = piece_color(piece.kind.0);
+    for &(x, y) in &piece.cells() {
+        if (0..40).contains(&y) {
+            colors[y as usize][x as usize] = color;
         }
-        if let Some(piece) = page.piece {
-            for &(x, y) in &to_libtetris(piece).cells() {
-                fill_tile(x as usize, y, piece.kind.into());
-            }
+    }
+    let mut y = 0;
+    for row in 0..40 {
+        if !cleared.contains(&(row as i32)) {
+            colors[y] = colors[row];
+            y += 1;
         }
-        writer.write_frame(&gif::Frame {
-            delay: (50.0 / speed).round() as u16,
-            width: BLOCK_SIZE as u16 * 10,
-            height: gif_height as u16,
-            buffer: buf.into(),
-            ..Default::default()
-        })?
     }
+    for row in colors.iter_mut().skip(y) {
+        *row = [fumen::CellColor::Empty; 10];
+    }
+}
 
-    drop(writer);
-
-    println!("gif is {} bytes large", gif_data.len());
+/// Pushes `board` and its `colors` up by `lines` rows of grey garbage with a hole in column
+/// `hole`, returning whether any blocks were pushed out of the top.
+fn raise_garbage(
+    board: &mut Board, colors: &mut [[fumen::CellColor; 10]; 40], lines: usize, hole: usize
+) -> bool {
+    let lines = lines.min(40);
+    let mut field = board.get_field();
+    let topped_out = field[40 - lines..].iter().any(|r| r.iter().any(|&c| c));
+    for y in (lines..40).rev() {
+        field[y] = field[y - lines];
+        colors[y] = colors[y - lines];
+    }
+    for y in 0..lines {
+        field[y] = [true; 10];
+        field[y][hole] = false;
+        colors[y] = [fumen::CellColor::Grey; 10];
+        colors[y][hole] = fumen::CellColor::Empty;
+    }
+    board.set_field(field);
+    topped_out
+}
 
-    Ok(gif_data)
+fn to_libtetris_field(field: &[[fumen::CellColor; 10]; 23]) -> [[bool; 10]; 40] {
+    let mut result = [[false; 10]; 40];
+    for y in 0..23 {
+        for x in 0..10 {
+            result[y][x] = field[y][x] != fumen::CellColor::Empty;
+        }
+    }
+    result
 }
 
 fn to_libtetris(p: fumen::Piece) -> libtetris::FallingPiece {
@@ -328,4 +1951,16 @@ fn from_char(c: char) -> Option<libtetris::Piece> {
         'Z' => Some(libtetris::Piece::Z),
         _ => None
     }
-}
\ No newline at end of file
+}
+
+fn to_char(p: libtetris::Piece) -> char {
+    match p {
+        libtetris::Piece::I => 'I',
+        libtetris::Piece::O => 'O',
+        libtetris::Piece::T => 'T',
+        libtetris::Piece::L => 'L',
+        libtetris::Piece::J => 'J',
+        libtetris::Piece::S => 'S',
+        libtetris::Piece::Z => 'Z',
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::{ HashMap, VecDeque };
+use std::hash::Hash;
+
+/// A map that forgets its oldest entries once it holds more than `capacity` of them.
+pub struct BoundedMap<K, V> {
+    entries: HashMap<K, V>,
+    /// Keys in the order they were inserted.
+    order: VecDeque<K>,
+    capacity: usize,
+}
+
+impl<K: Copy + Eq + Hash, V> BoundedMap<K, V> {
+    pub fn new(capacity: usize) -> BoundedMap<K, V> {
+        BoundedMap { entries: HashMap::new(), order: VecDeque::new(), capacity }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.entries.insert(key, value).is_some() {
+            self.order.retain(|&k| k != key);
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            let oldest = self.order.pop_front().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let value = self.entries.remove(key)?;
+        self.order.retain(|k| k != key);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_oldest() {
+        let mut map = BoundedMap::new(2);
+        map.insert(1, "a");
+        map.insert(2, "b");
+        map.insert(1, "c");
+        map.insert(3, "d");
+        assert_eq!(map.get(&2), None);
+        assert_eq!(map.get(&1), Some(&"c"));
+        assert_eq!(map.remove(&3), Some("d"));
+        assert_eq!(map.remove(&3), None);
+    }
+}
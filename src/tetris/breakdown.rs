@@ -0,0 +1,170 @@
+use cold_clear::evaluation::Standard;
+use libtetris::{ Board, FallingPiece, Piece, RotationState, TspinStatus };
+
+/// One term of the Standard evaluator's board value: how much of the feature the board has, and
+/// the weight it is multiplied by.
+pub struct Term {
+    pub name: &'static str,
+    pub amount: i32,
+    pub weight: i32,
+}
+
+impl Term {
+    pub fn contribution(&self) -> i32 {
+        self.amount * self.weight
+    }
+}
+
+/// Splits the Standard evaluator's value of `board` into its terms, always in the same order,
+/// following the shape features of its `evaluate`. Rewards for the placement itself (clears, spins,
+/// combo) are left out, as a board on its own doesn't have them. T-slots are counted where a T
+/// dropped pointing down would fit with three corners filled and clear lines, which approximates
+/// but doesn't match the evaluator's own sky and cave T-slot search.
+pub fn breakdown(eval: &Standard, board: &Board) -> Vec<Term> {
+    let heights = board.column_heights();
+    let highest = *heights.iter().max().unwrap();
+    let mut terms = vec![];
+    let mut term = |name, amount, weight| terms.push(Term { name, amount, weight });
+
+    term("back_to_back", board.b2b_bonus as i32, eval.back_to_back);
+    term("height", highest, eval.height);
+    term("top_half", (highest - 10).max(0), eval.top_half);
+    term("top_quarter", (highest - 15).max(0), eval.top_quarter);
+
+    let tslots = t_slots(board);
+    term("tslot1", tslots[1], eval.tslot[1]);
+    term("tslot2", tslots[2], eval.tslot[2]);
+    term("tslot3", tslots[3], eval.tslot[3]);
+
+    let well = (0..10).fold(0, |well, x| if heights[x] <= heights[well] { x } else { well });
+    let mut depth = 0;
+    'rows: for y in heights[well]..20 {
+        for x in 0..10 {
+            if x != well && !board.occupied(x as i32, y) {
+                break 'rows
+            }
+        }
+        depth += 1;
+    }
+    let depth = depth.min(eval.max_well_depth);
+    term("well_depth", depth, eval.well_depth);
+    term("well_column", (depth != 0) as i32, eval.well_column[well]);
+
+    let transitions = (0..40).map(|y| {
+        let mut transitions = 0;
+        let mut last = true;
+        for x in 0..10 {
+            if board.occupied(x, y) != last {
+                transitions += 1;
+                last = !last;
+            }
+        }
+        transitions + !last as i32
+    }).sum();
+    term("row_transitions", transitions, eval.row_transitions);
+
+    let (bumpiness, bumpiness_sq) = bumpiness(heights, well);
+    term("bumpiness", bumpiness, eval.bumpiness);
+    term("bumpiness_sq", bumpiness_sq, eval.bumpiness_sq);
+
+    let (cavities, overhangs) = cavities_and_overhangs(board);
+    term("cavity_cells", cavities, eval.cavity_cells);
+    term("cavity_cells_sq", cavities * cavities, eval.cavity_cells_sq);
+    term("overhang_cells", overhangs, eval.overhang_cells);
+    term("overhang_cells_sq", overhangs * overhangs, eval.overhang_cells_sq);
+
+    let (covered, covered_sq) = covered_cells(board);
+    term("covered_cells", covered, eval.covered_cells);
+    term("covered_cells_sq", covered_sq, eval.covered_cells_sq);
+    terms
+}
+
+/// Sum of the height differences between neighbouring columns, and of their squares, skipping the
+/// well.
+fn bumpiness(heights: &[i32; 10], well: usize) -> (i32, i32) {
+    let mut bumpiness = -1;
+    let mut bumpiness_sq = -1;
+    let mut prev = if well == 0 { 1 } else { 0 };
+    for x in 1..10 {
+        if x == well {
+            continue
+        }
+        let dh = (heights[prev] - heights[x]).abs();
+        bumpiness += dh;
+        bumpiness_sq += dh * dh;
+        prev = x;
+    }
+    (bumpiness.abs(), bumpiness_sq.abs())
+}
+
+/// Empty cells under the stack: overhangs can be reached by sliding in from the side, cavities
+/// can't.
+fn cavities_and_overhangs(board: &Board) -> (i32, i32) {
+    let heights = board.column_heights();
+    let mut cavities = 0;
+    let mut overhangs = 0;
+    for y in 0..*heights.iter().max().unwrap() {
+        for x in 0..10 {
+            if board.occupied(x as i32, y) || y >= heights[x] {
+                continue
+            }
+            if x > 1 && heights[x - 1] <= y - 1 && heights[x - 2] <= y {
+                overhangs += 1;
+                continue
+            }
+            if x < 8 && heights[x + 1] <= y - 1 && heights[x + 2] <= y {
+                overhangs += 1;
+                continue
+            }
+            cavities += 1;
+        }
+    }
+    (cavities, overhangs)
+}
+
+/// Filled cells above each hole, counting at most 6 per hole, and the sum of their squares.
+fn covered_cells(board: &Board) -> (i32, i32) {
+    let mut covered = 0;
+    let mut covered_sq = 0;
+    for x in 0..10 {
+        let height = board.column_heights()[x];
+        for y in (0..(height - 2).max(0)).rev() {
+            if !board.occupied(x as i32, y) {
+                let cells = 6.min(height - y - 1);
+                covered += cells;
+                covered_sq += cells * cells;
+            }
+        }
+    }
+    (covered, covered_sq)
+}
+
+/// Counts the T-slots on the board by how many lines a T placed in them would clear.
+fn t_slots(board: &Board) -> [i32; 4] {
+    let mut slots = [0; 4];
+    for x in 1..9 {
+        for y in 1..21 {
+            let piece = FallingPiece {
+                kind: libtetris::PieceState(Piece::T, RotationState::South),
+                x,
+                y,
+                tspin: TspinStatus::Full,
+            };
+            if board.obstructed(&piece) || !board.obstructed(&FallingPiece { y: y - 1, ..piece }) {
+                continue
+            }
+            let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter()
+                .filter(|&&(dx, dy)| board.occupied(x + dx, y + dy))
+                .count();
+            if corners < 3 {
+                continue
+            }
+            let mut after = board.clone();
+            let lines = after.lock_piece(piece).cleared_lines.len();
+            if lines > 0 {
+                slots[lines.min(3)] += 1;
+            }
+        }
+    }
+    slots
+}
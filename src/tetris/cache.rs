@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use sha2::{ Digest, Sha256 };
+
+use super::EXTRACT_OPTIONS;
+
+/// Content-addressed cache of rendered GIFs and analyses, kept in memory and in `cache/`.
+///
+/// Sizes are limited by `CACHE_MEMORY_MB` (default 64) and `CACHE_DISK_MB` (default 512), and the
+/// least recently used entries are evicted first.
+pub static CACHE: Lazy<Cache> = Lazy::new(|| {
+    let read = |var: &str, default: u64| std::env::var(var).ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default) * 1024 * 1024;
+    Cache::open(PathBuf::from("cache"), read("CACHE_MEMORY_MB", 64), read("CACHE_DISK_MB", 512))
+});
+
+/// Bump when rendering or analysis output changes so that stale entries are not served.
+const CACHE_VERSION: &str = "1";
+
+pub struct Cache {
+    dir: PathBuf,
+    max_memory: u64,
+    max_disk: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<String, Entry>,
+    clock: u64,
+    memory_size: u64,
+    disk_size: u64,
+}
+
+struct Entry {
+    size: u64,
+    last_used: u64,
+    data: Option<Vec<u8>>,
+}
+
+/// Builds a cache key from the kind of output and everything that determines it.
+///
+/// `options` is normalized so that reordering `#speed=2&foo=bar` style options still hits.
+pub fn key(kind: &str, fumen: &fumen::Fumen, options: &str) -> String {
+    let mut options: Vec<_> = EXTRACT_OPTIONS.find_iter(options).map(|m| m.as_str()).collect();
+    options.sort_unstable();
+    let mut hasher = Sha256::new();
+    for part in &[CACHE_VERSION, kind, fumen.encode().as_str(), options.join("&").as_str()] {
+        hasher.update(part.as_bytes());
+        hasher.update(&[0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+impl Cache {
+    fn open(dir: PathBuf, max_memory: u64, max_disk: u64) -> Self {
+        let mut state = State::default();
+        if let Ok(files) = std::fs::read_dir(&dir) {
+            let mut found: Vec<_> = files.filter_map(|f| {
+                let f = f.ok()?;
+                let meta = f.metadata().ok()?;
+                let modified = meta.modified().ok()?;
+                Some((modified, f.file_name().into_string().ok()?, meta.len()))
+            }).collect();
+            found.sort();
+            for (_, name, size) in found {
+                state.clock += 1;
+                state.disk_size += size;
+                state.entries.insert(name, Entry { size, last_used: state.clock, data: None });
+            }
+        }
+        Cache { dir, max_memory, max_disk, state: Mutex::new(state) }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.clock += 1;
+            let clock = state.clock;
+            let entry = state.entries.get_mut(key)?;
+            entry.last_used = clock;
+            if let Some(data) = &entry.data {
+                return Some(data.clone())
+            }
+        }
+        let data = match tokio::fs::read(self.dir.join(key)).await {
+            Ok(data) => data,
+            Err(_) => {
+                let mut state = self.state.lock().unwrap();
+                if let Some(entry) = state.entries.remove(key) {
+                    state.disk_size -= entry.size;
+                }
+                return None
+            }
+        };
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        if let Some(entry) = state.entries.get_mut(key) {
+            if entry.data.is_none() {
+                entry.data = Some(data.clone());
+                state.memory_size += entry.size;
+            }
+        }
+        self.evict(state);
+        Some(data)
+    }
+
+    pub async fn insert(&self, key: &str, data: Vec<u8>) {
+        if let Err(e) = tokio::fs::create_dir_all(&self.dir).await {
+            println!("Failed to create cache directory: {}", e);
+            return
+        }
+        if let Err(e) = tokio::fs::write(self.dir.join(key), &data).await {
+            println!("Failed to write cache entry: {}", e);
+            return
+        }
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let size = data.len() as u64;
+        let entry = Entry { size, last_used: state.clock, data: Some(data) };
+        if let Some(old) = state.entries.insert(key.to_owned(), entry) {
+            state.disk_size -= old.size;
+            if old.data.is_some() {
+                state.memory_size -= old.size;
+            }
+        }
+        state.disk_size += size;
+        state.memory_size += size;
+        self.evict(&mut state);
+    }
+
+    fn evict(&self, state: &mut State) {
+        while state.memory_size > self.max_memory {
+            let oldest = state.entries.iter_mut()
+                .filter(|(_, e)| e.data.is_some())
+                .min_by_key(|(_, e)| e.last_used);
+            match oldest {
+                Some((_, entry)) => {
+                    entry.data = None;
+                    state.memory_size -= entry.size;
+                }
+                None => break
+            }
+        }
+        while state.disk_size > self.max_disk {
+            let oldest = state.entries.iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            let key = match oldest {
+                Some(key) => key,
+                None => break
+            };
+            let entry = state.entries.remove(&key).unwrap();
+            state.disk_size -= entry.size;
+            if entry.data.is_some() {
+                state.memory_size -= entry.size;
+            }
+            let _ = std::fs::remove_file(self.dir.join(&key));
+        }
+    }
+}
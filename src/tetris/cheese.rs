@@ -0,0 +1,41 @@
+use fumen::{ CellColor, Fumen };
+
+use super::queue::{ queue_string, Bag, Rng };
+
+/// Highest garbage stack that still leaves room to spawn and play above it.
+pub const MAX_ROWS: usize = 18;
+/// Pieces in the generated queue comment: the current piece and six previews.
+const QUEUE_LENGTH: usize = 7;
+
+/// Settings for a cheese map. `messiness` is the percent chance that the hole moves to a new
+/// column from one row to the next.
+pub struct Cheese {
+    pub rows: usize,
+    pub messiness: u32,
+    pub seed: u64,
+}
+
+impl Cheese {
+    /// Builds a one page fumen with the garbage at the bottom of the field and a `#Q=` comment
+    /// drawn from a 7-bag, so that it can be passed straight to `-cc`.
+    pub fn generate(&self) -> Fumen {
+        let mut rng = Rng::new(self.seed);
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        let mut hole = rng.below(10);
+        for y in 0..self.rows.min(MAX_ROWS) {
+            if y > 0 && rng.below(100) < self.messiness as usize {
+                hole = (hole + 1 + rng.below(9)) % 10;
+            }
+            page.field[y] = [CellColor::Grey; 10];
+            page.field[y][hole] = CellColor::Empty;
+        }
+
+        let mut bag = Bag::new(self.seed);
+        let queue: Vec<_> = (0..QUEUE_LENGTH).map(|_| bag.next()).collect();
+        page.comment = Some(format!(
+            "#Q=[]({}){}", queue_string(&queue[..1]), queue_string(&queue[1..])
+        ));
+        fumen
+    }
+}
@@ -0,0 +1,84 @@
+use fumen::{ CellColor, Fumen, Page };
+
+use super::render::{ self, Canvas, BLOCK_SIZE };
+use super::to_libtetris;
+
+const GAP: usize = BLOCK_SIZE;
+/// Pixels per dot of the label letters, which are 3 by 5 dots.
+const LABEL_SCALE: usize = 4;
+const LABELS: [[&str; 5]; 2] = [
+    ["###", "#.#", "###", "#.#", "#.#"],
+    ["##.", "#.#", "##.", "#.#", "##."],
+];
+
+/// Renders two fumens side by side, labelled A and B, with their pages lined up by index. The
+/// shorter fumen holds its last page, and cells that differ between the two pages shown are
+/// outlined.
+pub fn render(fumens: [&Fumen; 2], speed: f64) -> Result<Vec<u8>, gif::EncodingError> {
+    let frames = fumens[0].pages.len().max(fumens[1].pages.len());
+    let page = |side: usize, i: usize| {
+        let pages = &fumens[side].pages;
+        &pages[i.min(pages.len() - 1)]
+    };
+    // One extra row at the top holds the labels.
+    let rows = 1 + fumens.iter()
+        .flat_map(|f| f.pages.iter().map(|p| render::field_rows(&p.field, p.piece)))
+        .max()
+        .unwrap_or(1)
+        .max(4);
+    let width = BLOCK_SIZE * 20 + GAP;
+    let height = render::pixel_height(rows, false);
+    let mut gif_data = vec![];
+    let mut writer = render::encoder(&mut gif_data, width, height)?;
+    for i in 0..frames {
+        let pages = [page(0, i), page(1, i)];
+        let filled = [cells(pages[0]), cells(pages[1])];
+        let mut canvas = Canvas::new(width, rows, false);
+        canvas.fill_rect(0, 0, width, height, render::BACKGROUND);
+        for side in 0..2 {
+            let left = side * (BLOCK_SIZE * 10 + GAP);
+            canvas.draw_page(left, pages[side]);
+            canvas.fill_rect(left, 0, BLOCK_SIZE * 10, BLOCK_SIZE, render::BACKGROUND);
+            draw_label(&mut canvas, left + BLOCK_SIZE * 5, side);
+            for y in 0..(rows - 1).min(23) {
+                for x in 0..10 {
+                    if filled[0][y][x] != filled[1][y][x] {
+                        canvas.outline_tile(left, x, y as i32, 3, render::WHITE);
+                    }
+                }
+            }
+        }
+        writer.write_frame(&canvas.into_frame(speed))?;
+    }
+    drop(writer);
+    Ok(gif_data)
+}
+
+/// The page's field with its piece placed in it.
+fn cells(page: &Page) -> [[CellColor; 10]; 23] {
+    let mut field = page.field;
+    if let Some(piece) = page.piece {
+        for &(x, y) in &to_libtetris(piece).cells() {
+            if (0..23).contains(&y) {
+                field[y as usize][x as usize] = piece.kind.into();
+            }
+        }
+    }
+    field
+}
+
+/// Draws label `index`, centered on `center` in the top row.
+fn draw_label(canvas: &mut Canvas, center: usize, index: usize) {
+    let left = center - 3 * LABEL_SCALE / 2;
+    let top = (BLOCK_SIZE - 5 * LABEL_SCALE) / 2;
+    for (y, line) in LABELS[index].iter().enumerate() {
+        for (x, dot) in line.chars().enumerate() {
+            if dot == '#' {
+                canvas.fill_rect(
+                    left + x * LABEL_SCALE, top + y * LABEL_SCALE, LABEL_SCALE, LABEL_SCALE,
+                    render::WHITE
+                );
+            }
+        }
+    }
+}
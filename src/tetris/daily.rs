@@ -0,0 +1,386 @@
+use std::collections::{ HashMap, VecDeque };
+use std::sync::Arc;
+use std::time::Duration;
+
+use cold_clear::evaluation::{ Evaluator, Standard };
+use fumen::Fumen;
+use libtetris::{ Board, FallingPiece, Piece };
+use once_cell::sync::Lazy;
+use serde::{ Deserialize, Serialize };
+use serenity::http::{ AttachmentType, Http };
+use serenity::model::id::{ GuildId, UserId };
+use tokio::sync::{ Mutex, OnceCell };
+
+use super::cheese::Cheese;
+use super::queue::{ queue_string, Bag };
+use super::reach::Search;
+use super::rotation::RotationSystem;
+use super::scheduler::SCHEDULER;
+use super::stats::guideline_attack;
+
+const DAILY_FILE: &str = "daily-puzzles.json";
+/// Pieces after the current one in perfect clear puzzles, enough for a 4 line PC with a spare.
+const PC_QUEUE: usize = 10;
+
+/// What a daily puzzle is scored on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Goal {
+    /// Most garbage sent with the whole queue.
+    Attack,
+    /// Best Cold Clear evaluation of the placements and the resulting board.
+    Evaluation,
+    PerfectClear,
+}
+
+impl Goal {
+    fn describe(self) -> &'static str {
+        match self {
+            Goal::Attack => "Send as much attack as you can with the queue",
+            Goal::Evaluation => "Build the best board Cold Clear can think of",
+            Goal::PerfectClear => "Get a perfect clear",
+        }
+    }
+}
+
+pub struct Puzzle {
+    pub day: i64,
+    pub fumen: Fumen,
+    pub goal: Goal,
+    hold: Option<Piece>,
+    current: Piece,
+    queue: Vec<Piece>,
+    /// Cold Clear's own score, which a submission has to match to count as solved. Filled in by
+    /// the first call to `target`.
+    target: OnceCell<Option<Score>>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Score {
+    pub pieces: u32,
+    pub attack: u32,
+    pub evaluation: i64,
+    pub perfect_clear: bool,
+}
+
+impl Puzzle {
+    /// The puzzle for a day, which is the same for every server.
+    fn generate(day: i64) -> Puzzle {
+        let seed = (day as u64).wrapping_mul(0x9E3779B97F4A7C15);
+        let goal = match day.rem_euclid(3) {
+            0 => Goal::Attack,
+            1 => Goal::Evaluation,
+            _ => Goal::PerfectClear
+        };
+        let mut bag = Bag::new(seed);
+        let (mut fumen, queue_length) = match goal {
+            Goal::PerfectClear => (Fumen::default(), PC_QUEUE),
+            _ => (Cheese { rows: 4, messiness: 30, seed }.generate(), 6)
+        };
+        if fumen.pages.is_empty() {
+            fumen.add_page();
+        }
+        let current = bag.next();
+        let queue: Vec<_> = (0..queue_length).map(|_| bag.next()).collect();
+        fumen.pages[0].comment = Some(format!(
+            "#Q=[]({}){}", queue_string(&[current]), queue_string(&queue)
+        ));
+        Puzzle { day, fumen, goal, hold: None, current, queue, target: OnceCell::new() }
+    }
+
+    fn board(&self) -> Board {
+        let field = super::to_libtetris_field(&self.fumen.pages[0].field);
+        let mut board = Board::new_with_state(field, Default::default(), self.hold, false, 0);
+        board.add_next_piece(self.current);
+        for &p in &self.queue {
+            board.add_next_piece(p);
+        }
+        board
+    }
+
+    /// Plays `placements` in order, checking that each one is reachable and uses the current or
+    /// hold piece.
+    pub fn score(&self, placements: &[FallingPiece]) -> Result<Score, String> {
+        if placements.is_empty() {
+            return Err("The answer doesn't place any pieces.".to_owned())
+        }
+        let mut board = self.board();
+        let evaluator = Standard::default();
+        let mut current = Some(self.current);
+        let mut hold = self.hold;
+        let mut queue: VecDeque<_> = self.queue.iter().copied().collect();
+        let mut score = Score { pieces: 0, attack: 0, evaluation: 0, perfect_clear: false };
+        for (i, &target) in placements.iter().enumerate() {
+            let kind = target.kind.0;
+            if current == Some(kind) {
+                current = queue.pop_front();
+            } else if hold.is_some() && hold == Some(kind) {
+                hold = current.take();
+                current = queue.pop_front();
+            } else if hold.is_none() && queue.front() == Some(&kind) {
+                hold = current;
+                queue.pop_front();
+                current = queue.pop_front();
+            } else {
+                return Err(format!("Placement {} uses a piece that isn't available.", i + 1))
+            }
+            let piece = Search::new(RotationSystem::Srs, &board, kind)
+                .and_then(|search| search.path_to(&target).map(|path| {
+                    path.last().map_or(search.spawn(), |&(_, piece)| piece)
+                }))
+                .ok_or_else(|| format!("Placement {} can't be reached with SRS.", i + 1))?;
+            let lock = board.lock_piece(piece);
+            let (value, reward) = evaluator.evaluate(&lock, &board, 0, kind);
+            score.pieces += 1;
+            score.attack += guideline_attack(&lock);
+            score.evaluation += reward.value as i64;
+            if i == placements.len() - 1 {
+                score.evaluation += value.value as i64;
+            }
+            if lock.perfect_clear {
+                score.perfect_clear = true;
+                break
+            }
+        }
+        Ok(score)
+    }
+
+    /// Cold Clear's score, searching for it on first use. Concurrent callers wait for the same
+    /// search rather than starting their own.
+    pub async fn target(&self) -> Option<&Score> {
+        if self.goal == Goal::PerfectClear {
+            return None
+        }
+        self.target.get_or_init(|| cold_clear_score(self)).await.as_ref()
+    }
+
+    fn known_target(&self) -> Option<&Score> {
+        self.target.get().and_then(Option::as_ref)
+    }
+
+    /// Whether `score` is good enough to count as solving the puzzle, or `None` if answers can't
+    /// be scored because Cold Clear's search failed. Call `target` first so that Cold Clear's score
+    /// is known.
+    pub fn solved_by(&self, score: &Score) -> Option<bool> {
+        match (self.goal, self.known_target()) {
+            (Goal::PerfectClear, _) => Some(score.perfect_clear),
+            (Goal::Attack, Some(target)) => Some(score.attack >= target.attack),
+            (Goal::Evaluation, Some(target)) => Some(score.evaluation >= target.evaluation),
+            (_, None) => None
+        }
+    }
+
+    /// A line comparing `score` with Cold Clear's.
+    pub fn describe(&self, score: &Score) -> String {
+        match (self.goal, self.known_target()) {
+            (Goal::PerfectClear, _) if score.perfect_clear => {
+                format!("Perfect clear in {} pieces", score.pieces)
+            }
+            (Goal::PerfectClear, _) => "No perfect clear".to_owned(),
+            (Goal::Attack, Some(target)) => {
+                format!("{} attack (Cold Clear: {})", score.attack, target.attack)
+            }
+            (Goal::Evaluation, Some(target)) => {
+                format!("{} evaluation (Cold Clear: {})", score.evaluation, target.evaluation)
+            }
+            (Goal::Attack, None) => format!("{} attack", score.attack),
+            (Goal::Evaluation, None) => format!("{} evaluation", score.evaluation),
+        }
+    }
+
+    pub fn announcement(&self) -> String {
+        format!(
+            "Daily puzzle #{}: {}. Answer with `-answer <fumen>`.\n{}",
+            self.day, self.goal.describe(), self.fumen.encode()
+        )
+    }
+}
+
+/// Scheduler owner of the daily puzzle's search; no Discord user has this id.
+const DAILY_JOB: UserId = UserId(0);
+
+/// Lets Cold Clear play the puzzle to find the score members have to match. The search waits for
+/// a scheduler slot like any other analysis.
+async fn cold_clear_score(puzzle: &Puzzle) -> Option<Score> {
+    let ticket = SCHEDULER.enqueue(DAILY_JOB).ok()?;
+    while ticket.wait_for_change().await.is_some() {}
+    if ticket.is_cancelled() {
+        return None
+    }
+    let cc = cold_clear::Interface::launch(puzzle.board(), cold_clear::Options {
+        speculate: false,
+        pcloop: None,
+        ..Default::default()
+    }, Standard::default(), None);
+    let mut placements = vec![];
+    // The current piece and the whole queue, like a member's answer.
+    for _ in 0..puzzle.queue.len() + 1 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cc.suggest_next_move(0);
+        tokio::task::yield_now().await;
+        match cc.block_next_move() {
+            Some((mv, _)) => {
+                cc.play_next_move(mv.expected_location);
+                placements.push(mv.expected_location);
+            }
+            None => break
+        }
+    }
+    puzzle.score(&placements).ok()
+}
+
+fn today() -> i64 {
+    chrono::Utc::now().timestamp().div_euclid(24 * 60 * 60)
+}
+
+static PUZZLE: Lazy<Mutex<Option<Arc<Puzzle>>>> = Lazy::new(Mutex::default);
+
+/// Today's puzzle, generating it on first use. Cold Clear's score is searched separately by
+/// `Puzzle::target`, so this never waits on the search.
+pub async fn puzzle() -> Arc<Puzzle> {
+    let mut current = PUZZLE.lock().await;
+    let day = today();
+    match &*current {
+        Some(puzzle) if puzzle.day == day => puzzle.clone(),
+        _ => {
+            let puzzle = Arc::new(Puzzle::generate(day));
+            *current = Some(puzzle.clone());
+            puzzle
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct GuildRecords {
+    last_posted: Option<i64>,
+    players: HashMap<UserId, Player>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Player {
+    solved: u32,
+    streak: u32,
+    best_streak: u32,
+    last_solved: Option<i64>,
+}
+
+impl Player {
+    /// The streak as of `day`, which lapses once a day goes by unsolved.
+    fn current_streak(&self, day: i64) -> u32 {
+        match self.last_solved {
+            Some(last) if last >= day - 1 => self.streak,
+            _ => 0
+        }
+    }
+}
+
+static RECORDS: Lazy<Mutex<HashMap<GuildId, GuildRecords>>> =
+    Lazy::new(|| match std::fs::File::open(DAILY_FILE) {
+        Ok(f) => {
+            let reader = std::io::BufReader::new(f);
+            let map = serde_json::from_reader(reader).unwrap_or_else(|_| HashMap::new());
+            Mutex::new(map)
+        }
+        Err(_) => Mutex::default(),
+    });
+
+fn write(records: &HashMap<GuildId, GuildRecords>) {
+    let f = std::fs::File::create(DAILY_FILE).unwrap();
+    let writer = std::io::BufWriter::new(f);
+    serde_json::to_writer(writer, records).unwrap();
+}
+
+/// Records that `user` solved the puzzle for `day`, returning their streak. Solving the same
+/// day again changes nothing.
+pub async fn record_solve(guild: GuildId, user: UserId, day: i64) -> u32 {
+    let mut records = RECORDS.lock().await;
+    let player = records.entry(guild).or_default().players.entry(user).or_default();
+    if player.last_solved != Some(day) {
+        player.streak = player.current_streak(day) + 1;
+        player.best_streak = player.best_streak.max(player.streak);
+        player.solved += 1;
+        player.last_solved = Some(day);
+        let streak = player.streak;
+        write(&records);
+        streak
+    } else {
+        player.streak
+    }
+}
+
+/// The top players of a server by puzzles solved, then current streak.
+pub async fn leaderboard(guild: GuildId) -> String {
+    let records = RECORDS.lock().await;
+    let day = today();
+    let mut players: Vec<_> = match records.get(&guild) {
+        Some(records) => records.players.iter().collect(),
+        None => vec![]
+    };
+    if players.is_empty() {
+        return "Nobody has solved a daily puzzle yet.".to_owned()
+    }
+    players.sort_by_key(|(_, p)| std::cmp::Reverse((p.solved, p.current_streak(day))));
+    let mut text = String::new();
+    for (rank, (user, player)) in players.iter().take(10).enumerate() {
+        text += &format!(
+            "{}. <@{}>: {} solved, streak {} (best {})\n",
+            rank + 1, user, player.solved, player.current_streak(day), player.best_streak
+        );
+    }
+    text
+}
+
+/// Posts the daily puzzle to each server's configured channel once a day.
+pub async fn run(http: Arc<Http>) {
+    loop {
+        let day = today();
+        let channels: Vec<_> = crate::CONFIGURATION.lock().await.iter()
+            .filter_map(|(&guild, config)| config.puzzle_channel.map(|channel| (guild, channel)))
+            .collect();
+        let mut pending = vec![];
+        {
+            let records = RECORDS.lock().await;
+            for (guild, channel) in channels {
+                let posted = records.get(&guild).and_then(|r| r.last_posted);
+                if posted != Some(day) {
+                    pending.push((guild, channel));
+                }
+            }
+        }
+        if !pending.is_empty() {
+            let puzzle = puzzle().await;
+            let render = puzzle.fumen.clone();
+            let gif = tokio::task::spawn_blocking(
+                move || super::render_fumen(render, "")
+            ).await.unwrap();
+            if let Ok(gif) = gif {
+                for (guild, channel) in pending {
+                    let sent = channel.send_files(&http, vec![AttachmentType::Bytes {
+                        data: gif.clone().into(),
+                        filename: "puzzle.gif".into()
+                    }], |f| f.content(puzzle.announcement())).await;
+                    if let Err(e) = sent {
+                        println!("Failed to post the daily puzzle: {}", e);
+                    }
+                    let mut records = RECORDS.lock().await;
+                    records.entry(guild).or_default().last_posted = Some(day);
+                    write(&records);
+                }
+            }
+            tokio::spawn(async move {
+                puzzle.target().await;
+            });
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    }
+}
+
+/// The placements in an answer, from the pages that have a piece.
+pub fn placements(answer: &Fumen) -> Vec<FallingPiece> {
+    answer.pages.iter().filter_map(|page| page.piece).map(super::to_libtetris).collect()
+}
+
+/// Whether an answer starts from the puzzle's field.
+pub fn same_start(puzzle: &Puzzle, answer: &Fumen) -> bool {
+    let start = super::to_libtetris_field(&puzzle.fumen.pages[0].field);
+    answer.pages.first().map_or(false, |page| super::to_libtetris_field(&page.field) == start)
+}
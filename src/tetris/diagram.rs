@@ -0,0 +1,211 @@
+use fumen::{ CellColor, Fumen, Page };
+use regex::Regex;
+
+use lazy_static::lazy_static;
+
+use super::{ from_char, piece_color };
+
+/// Reads Hard Drop wiki playfield diagrams, a `{{pfstart}}` line, a `{{pfrow|...}}` line for each
+/// row from the top down and a `{{pfend}}` line. Each diagram in `text` becomes a page.
+pub fn parse_wiki(text: &str) -> Option<Fumen> {
+    lazy_static! {
+        static ref DIAGRAM: Regex = Regex::new(r"(?is)\{\{pfstart\}\}(.*?)\{\{pfend\}\}").unwrap();
+        static ref PFROW: Regex = Regex::new(r"(?i)\{\{pfrow((?:\|[^|}]*){10})\}\}").unwrap();
+    }
+    let mut fumen = Fumen::default();
+    for diagram in DIAGRAM.captures_iter(text) {
+        let rows = PFROW.captures_iter(diagram.get(1).unwrap().as_str())
+            .map(|caps| caps.get(1).unwrap().as_str()[1..]
+                .split('|')
+                .map(|cell| cell_color(cell.trim()))
+                .collect::<Option<Vec<_>>>()
+            )
+            .collect::<Option<Vec<_>>>()?;
+        add_page(&mut fumen, &rows)?;
+    }
+    if fumen.pages.is_empty() {
+        None
+    } else {
+        Some(fumen)
+    }
+}
+
+/// Reads four.lol style text grids inside a code block: rows of ten cells from the top down, `_`
+/// for empty, `X` for garbage and the piece letter otherwise. Blank lines separate pages.
+pub fn parse_grid(text: &str) -> Option<Fumen> {
+    lazy_static! {
+        static ref CODE_BLOCK: Regex = Regex::new(r"(?s)```\w*\n(.*?)```").unwrap();
+    }
+    let block = CODE_BLOCK.captures(text)?.get(1).unwrap().as_str();
+    let mut fumen = Fumen::default();
+    for grid in block.split("\n\n").filter(|grid| !grid.trim().is_empty()) {
+        let rows = grid.lines()
+            .map(|line| {
+                let line = line.trim();
+                if line.chars().count() != 10 {
+                    return None
+                }
+                line.chars().map(|c| cell_color(&c.to_string())).collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        // A single row of text is much more likely to be something else.
+        if rows.len() < 2 {
+            return None
+        }
+        add_page(&mut fumen, &rows)?;
+    }
+    if fumen.pages.is_empty() {
+        None
+    } else {
+        Some(fumen)
+    }
+}
+
+/// Writes each page's field as a Hard Drop wiki diagram.
+pub fn to_wiki(fumen: &Fumen) -> String {
+    let mut text = String::new();
+    for page in &fumen.pages {
+        text += "{{pfstart}}\n";
+        for row in visible_rows(page) {
+            text += "{{pfrow";
+            for &cell in row {
+                text.push('|');
+                text.push(match cell {
+                    CellColor::Empty => ' ',
+                    CellColor::Grey => 'G',
+                    c => cell_char(c)
+                });
+            }
+            text += "}}\n";
+        }
+        text += "{{pfend}}\n";
+    }
+    text
+}
+
+/// Writes each page's field as a four.lol text grid, with a blank line between pages.
+pub fn to_grid(fumen: &Fumen) -> String {
+    fumen.pages.iter()
+        .map(|page| visible_rows(page)
+            .map(|row| row.iter().map(|&c| cell_char(c)).collect::<String>() + "\n")
+            .collect::<String>()
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The rows of the page's field from the highest filled one (or the fourth, if lower) down.
+fn visible_rows(page: &Page) -> impl Iterator<Item=&[CellColor; 10]> {
+    let top = page.field.iter()
+        .rposition(|row| row.iter().any(|&c| c != CellColor::Empty))
+        .unwrap_or(0)
+        .max(3);
+    page.field[..=top].iter().rev()
+}
+
+/// Adds a page with `rows` given from the top down, resting on the floor.
+fn add_page(fumen: &mut Fumen, rows: &[Vec<CellColor>]) -> Option<()> {
+    let empty = rows.iter().all(|row| row.iter().all(|&c| c == CellColor::Empty));
+    if empty || rows.len() > 23 {
+        return None
+    }
+    let page = fumen.add_page();
+    for (y, row) in rows.iter().rev().enumerate() {
+        page.field[y].copy_from_slice(row);
+    }
+    Some(())
+}
+
+fn cell_color(cell: &str) -> Option<CellColor> {
+    match cell.to_ascii_uppercase().as_str() {
+        "" | "_" | "." => Some(CellColor::Empty),
+        "G" | "X" | "#" => Some(CellColor::Grey),
+        piece => {
+            let mut chars = piece.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => from_char(c).map(piece_color),
+                _ => None
+            }
+        }
+    }
+}
+
+fn cell_char(color: CellColor) -> char {
+    match color {
+        CellColor::Empty => '_',
+        CellColor::Grey => 'X',
+        CellColor::I => 'I',
+        CellColor::O => 'O',
+        CellColor::T => 'T',
+        CellColor::L => 'L',
+        CellColor::J => 'J',
+        CellColor::S => 'S',
+        CellColor::Z => 'Z',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIKI: &str = "\
+{{pfstart}}
+{{pfrow| | | | | | | | | | }}
+{{pfrow| | | | | | | | | | }}
+{{pfrow|T| | | | | | | | | }}
+{{pfrow|T|T|Z|Z| | | |G|G|G}}
+{{pfend}}
+";
+
+    const GRID: &str = "\
+__________
+__________
+T_________
+TTZZ___XXX
+";
+
+    fn fields(fumen: &Fumen) -> Vec<[[CellColor; 10]; 23]> {
+        fumen.pages.iter().map(|page| page.field).collect()
+    }
+
+    #[test]
+    fn wiki_round_trip() {
+        let fumen = parse_wiki(&format!("A diagram:\n{}and some text", WIKI)).unwrap();
+        assert_eq!(fumen.pages.len(), 1);
+        let field = fumen.pages[0].field;
+        assert_eq!(field[0][..4], [CellColor::T, CellColor::T, CellColor::Z, CellColor::Z]);
+        assert_eq!(field[0][7..], [CellColor::Grey; 3]);
+        assert_eq!(field[1][0], CellColor::T);
+        assert_eq!(to_wiki(&fumen), WIKI);
+        assert_eq!(to_grid(&fumen), GRID);
+    }
+
+    #[test]
+    fn grid_round_trip() {
+        let text = format!("```\n{}\n{}```", GRID, GRID.replace('T', "_"));
+        let fumen = parse_grid(&text).unwrap();
+        assert_eq!(fumen.pages.len(), 2);
+        assert_eq!(fumen.pages[1].field[1][0], CellColor::Empty);
+        assert_eq!(to_wiki(&fumen), format!("{}{}", WIKI, WIKI.replace('T', " ")));
+        let again = parse_grid(&format!("```\n{}```", to_grid(&fumen))).unwrap();
+        assert_eq!(fields(&again), fields(&fumen));
+    }
+
+    #[test]
+    fn not_diagrams() {
+        let code = [
+            "```rust\nfn main() {\n    println!(\"hello\");\n}\n```",
+            "```\nlet x = 1;\nlet y = 2;\n```",
+            "```\nXXXXXXXXX_\n```",
+            "```\n__________\n__________\n```",
+            "__________\nTTZZ___XXX",
+        ];
+        for text in code {
+            assert!(parse_grid(text).is_none(), "{} parsed", text);
+        }
+        let wiki = ["{{pfstart}}{{pfend}}", "{{pfstart}}\n{{pfrow|T|T}}\n{{pfend}}", "{{pfrow|T}}"];
+        for text in wiki {
+            assert!(parse_wiki(text).is_none(), "{} parsed", text);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use cold_clear::evaluation::Standard;
+
+use super::EXTRACT_OPTIONS;
+
+/// Named starting points for evaluator settings.
+pub const PRESETS: &[&str] = &["default", "fast"];
+
+pub fn preset(name: &str) -> Option<Standard> {
+    match name {
+        "default" => Some(Standard::default()),
+        "fast" => Some(Standard::fast_config()),
+        _ => None
+    }
+}
+
+macro_rules! weights {
+    ($($name:ident),*) => {
+        /// The scalar weights of the Standard evaluator that can be changed by name.
+        pub const WEIGHTS: &[&str] = &[$(stringify!($name)),*];
+
+        pub fn weight_mut<'a>(eval: &'a mut Standard, name: &str) -> Option<&'a mut i32> {
+            match name {
+                $(stringify!($name) => Some(&mut eval.$name),)*
+                _ => None
+            }
+        }
+
+        pub fn weight(eval: &Standard, name: &str) -> Option<i32> {
+            match name {
+                $(stringify!($name) => Some(eval.$name),)*
+                _ => None
+            }
+        }
+    };
+}
+
+weights!(
+    back_to_back, bumpiness, bumpiness_sq, row_transitions, height, top_half, top_quarter,
+    jeopardy, cavity_cells, cavity_cells_sq, overhang_cells, overhang_cells_sq, covered_cells,
+    covered_cells_sq, well_depth, max_well_depth, b2b_clear, clear1, clear2, clear3, clear4,
+    tspin1, tspin2, tspin3, mini_tspin1, mini_tspin2, perfect_clear, combo_garbage, move_time,
+    wasted_t
+);
+
+/// Builds evaluator settings from options such as `p1=fast p1.tspin2=500`, where `prefix` is
+/// `p1` in that example. With an empty prefix the options are `eval=fast tspin2=500`.
+pub fn from_options(prefix: &str, options: &str) -> Result<Standard, String> {
+    let preset_key = if prefix.is_empty() { "eval".to_owned() } else { prefix.to_owned() };
+    let weight_prefix = if prefix.is_empty() { String::new() } else { format!("{}.", prefix) };
+    let mut eval = Standard::default();
+    for caps in EXTRACT_OPTIONS.captures_iter(options) {
+        let key = caps.get(1).unwrap().as_str();
+        let value = caps.get(2).unwrap().as_str();
+        if key == preset_key {
+            eval = preset(value).ok_or_else(|| format!(
+                "Unknown evaluator preset `{}`. Try one of: {}", value, PRESETS.join(", ")
+            ))?;
+        }
+    }
+    for caps in EXTRACT_OPTIONS.captures_iter(options) {
+        let key = caps.get(1).unwrap().as_str();
+        let value = caps.get(2).unwrap().as_str();
+        let name = match key.strip_prefix(&*weight_prefix) {
+            Some(name) if !name.contains('.') => name,
+            _ => continue
+        };
+        if let Some(weight) = weight_mut(&mut eval, name) {
+            *weight = value.parse().map_err(|_| format!("`{}` is not a valid weight", value))?;
+        }
+    }
+    Ok(eval)
+}
@@ -0,0 +1,58 @@
+use libtetris::{ FallingPiece, LockResult, TspinStatus };
+use serde::Serialize;
+
+/// One of Cold Clear's placements in a `-cc` analysis, as written to the `json=yes` attachment.
+/// Coordinates and rotation are those of the fumen page the placement is drawn on.
+#[derive(Serialize)]
+pub struct Placement {
+    pub piece: char,
+    pub rotation: &'static str,
+    pub x: u32,
+    pub y: u32,
+    pub hold: bool,
+    /// `none`, `mini` or `full`.
+    pub tspin: &'static str,
+    pub lines_cleared: usize,
+    pub nodes: u64,
+    pub depth: u32,
+    /// The Standard evaluator's value for the resulting board plus the reward for the placement.
+    pub evaluation: i32,
+}
+
+impl Placement {
+    pub fn new(
+        piece: FallingPiece, hold: bool, lock: &LockResult, info: &cold_clear::Info, evaluation: i32
+    ) -> Placement {
+        let location = super::to_fumen(piece);
+        let (nodes, depth) = match info {
+            cold_clear::Info::Normal(info) => (info.nodes as u64, info.depth as u32),
+            _ => (0, 0)
+        };
+        Placement {
+            piece: super::to_char(piece.kind.0),
+            rotation: match location.rotation {
+                fumen::RotationState::North => "north",
+                fumen::RotationState::East => "east",
+                fumen::RotationState::South => "south",
+                fumen::RotationState::West => "west",
+            },
+            x: location.x,
+            y: location.y,
+            hold,
+            tspin: match piece.tspin {
+                TspinStatus::None => "none",
+                TspinStatus::Mini => "mini",
+                TspinStatus::Full => "full",
+            },
+            lines_cleared: lock.cleared_lines.len(),
+            nodes,
+            depth,
+            evaluation,
+        }
+    }
+}
+
+/// The placements as a pretty-printed JSON array.
+pub fn to_json(placements: &[Placement]) -> Vec<u8> {
+    serde_json::to_vec_pretty(placements).unwrap()
+}
@@ -0,0 +1,154 @@
+use std::collections::{ HashMap, VecDeque };
+
+use libtetris::{ Board, FallingPiece, PieceMovement, RotationState };
+
+use super::rotation::{ rotated, RotationSystem };
+use super::same_placement;
+
+/// A single key press as a player would count it for finesse.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Input {
+    Left,
+    Right,
+    DasLeft,
+    DasRight,
+    Cw,
+    Ccw,
+    Flip,
+    SoftDrop,
+    HardDrop,
+}
+
+const MOVES: [Input; 8] = [
+    Input::DasLeft, Input::DasRight, Input::Left, Input::Right, Input::Cw, Input::Ccw,
+    Input::Flip, Input::SoftDrop
+];
+
+impl Input {
+    pub fn name(self) -> &'static str {
+        match self {
+            Input::Left => "L",
+            Input::Right => "R",
+            Input::DasLeft => "DAS L",
+            Input::DasRight => "DAS R",
+            Input::Cw => "CW",
+            Input::Ccw => "CCW",
+            Input::Flip => "180",
+            Input::SoftDrop => "softdrop",
+            Input::HardDrop => "hard drop",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Input> {
+        Some(match &*text.to_ascii_lowercase() {
+            "l" | "left" => Input::Left,
+            "r" | "right" => Input::Right,
+            "dl" | "dasl" | "das_l" => Input::DasLeft,
+            "dr" | "dasr" | "das_r" => Input::DasRight,
+            "cw" => Input::Cw,
+            "ccw" => Input::Ccw,
+            "180" | "flip" => Input::Flip,
+            "sd" | "softdrop" => Input::SoftDrop,
+            "hd" | "harddrop" => Input::HardDrop,
+            _ => return None
+        })
+    }
+
+    /// Applies the input, returning whether the piece moved.
+    pub fn apply(self, piece: &mut FallingPiece, board: &Board, rs: RotationSystem) -> bool {
+        let state = piece.kind.1;
+        match self {
+            Input::Left => piece.shift(board, -1, 0),
+            Input::Right => piece.shift(board, 1, 0),
+            Input::DasLeft => {
+                let mut moved = false;
+                while piece.shift(board, -1, 0) {
+                    moved = true;
+                }
+                moved
+            }
+            Input::DasRight => {
+                let mut moved = false;
+                while piece.shift(board, 1, 0) {
+                    moved = true;
+                }
+                moved
+            }
+            Input::Cw => rs.rotate(piece, board, rotated(state, 1)),
+            Input::Ccw => rs.rotate(piece, board, rotated(state, 3)),
+            Input::Flip => rs.has_180() && rs.rotate(piece, board, rotated(state, 2)),
+            Input::SoftDrop | Input::HardDrop => piece.sonic_drop(board),
+        }
+    }
+}
+
+pub fn movement_name(movement: PieceMovement) -> &'static str {
+    match movement {
+        PieceMovement::Left => "L",
+        PieceMovement::Right => "R",
+        PieceMovement::Cw => "CW",
+        PieceMovement::Ccw => "CCW",
+        PieceMovement::SonicDrop => "softdrop",
+    }
+}
+
+/// Formats the inputs Cold Clear uses for a move, e.g. `L, L, CW, softdrop, hard drop`.
+pub fn describe_move(mv: &libtetris::Move) -> String {
+    let mut keys = vec![];
+    if mv.hold {
+        keys.push("hold");
+    }
+    keys.extend(mv.inputs.iter().map(|&m| movement_name(m)));
+    keys.push("hard drop");
+    keys.join(", ")
+}
+
+pub fn describe_inputs(inputs: &[Input]) -> String {
+    inputs.iter().map(|i| i.name()).collect::<Vec<_>>().join(", ")
+}
+
+/// Finds the shortest input sequence that hard drops a piece from spawn into `target`.
+///
+/// Auto-shift to the wall counts as one input, as is usual when counting finesse.
+pub fn finesse(rs: RotationSystem, board: &Board, target: FallingPiece) -> Option<Vec<Input>> {
+    let spawn = rs.spawn(target.kind.0, board)?;
+    let mut parents: HashMap<Key, Option<(Key, Input)>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    parents.insert(key(&spawn), None);
+    queue.push_back(spawn);
+    while let Some(piece) = queue.pop_front() {
+        let mut dropped = piece;
+        dropped.sonic_drop(board);
+        if same_placement(dropped, target) {
+            let mut inputs = vec![Input::HardDrop];
+            let mut current = key(&piece);
+            while let Some((parent, input)) = parents[&current] {
+                inputs.push(input);
+                current = parent;
+            }
+            inputs.reverse();
+            return Some(inputs)
+        }
+        for &input in &MOVES {
+            let mut next = piece;
+            if input.apply(&mut next, board, rs) && !parents.contains_key(&key(&next)) {
+                parents.insert(key(&next), Some((key(&piece), input)));
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// A piece's position and rotation, for remembering where searches have been.
+pub type Key = (i32, i32, u8);
+
+pub fn key(piece: &FallingPiece) -> Key {
+    let rotation = match piece.kind.1 {
+        RotationState::North => 0,
+        RotationState::East => 1,
+        RotationState::South => 2,
+        RotationState::West => 3,
+    };
+    (piece.x, piece.y, rotation)
+}
@@ -0,0 +1,202 @@
+use fumen::CellColor;
+use libtetris::Board;
+use regex::Regex;
+
+use lazy_static::lazy_static;
+
+use super::EXTRACT_OPTIONS;
+use super::queue::{ random_seed, Rng };
+use super::render::{ self, Canvas, BLOCK_SIZE };
+
+pub const METER_WIDTH: usize = BLOCK_SIZE / 2;
+
+/// Garbage that rises during a `-cc` analysis, given as `garbage=3@2,4@5`: 3 lines after the
+/// second placement and 4 after the fifth. Garbage always arrives in full; line clears don't
+/// cancel it.
+pub struct Schedule {
+    batches: Vec<Batch>,
+    /// The fumen's garbage row, used as the pattern of every garbage line if it isn't empty.
+    pattern: Option<[CellColor; 10]>,
+    holes: Rng,
+    seed: u64,
+}
+
+struct Batch {
+    lines: u32,
+    after: usize,
+}
+
+impl Schedule {
+    pub fn none() -> Schedule {
+        Schedule { batches: vec![], pattern: None, holes: Rng::new(0), seed: 0 }
+    }
+
+    /// Reads the schedule from the options. Without a garbage row in the fumen, each batch gets a
+    /// random hole column, which `seed=` fixes.
+    pub fn parse(options: &str, garbage_row: &[CellColor; 10]) -> Result<Schedule, String> {
+        lazy_static! {
+            static ref GARBAGE: Regex = Regex::new(r"garbage=([0-9@,]+)").unwrap();
+        }
+        let mut schedule = Schedule::none();
+        let batches = match GARBAGE.captures(options) {
+            Some(caps) => caps.get(1).unwrap().as_str(),
+            None => return Ok(schedule)
+        };
+        for batch in batches.split(',').filter(|b| !b.is_empty()) {
+            let (lines, after) = batch.split_once('@')
+                .and_then(|(lines, after)| Some((lines.parse().ok()?, after.parse().ok()?)))
+                .filter(|&(lines, _)| lines > 0 && lines <= 20)
+                .ok_or_else(|| format!("Invalid garbage `{}`, expected e.g. `3@2`", batch))?;
+            schedule.batches.push(Batch { lines, after });
+        }
+
+        if garbage_row.iter().any(|&c| c != CellColor::Empty) {
+            schedule.pattern = Some(*garbage_row);
+        }
+        let mut seed = random_seed();
+        for caps in EXTRACT_OPTIONS.captures_iter(options) {
+            if caps.get(1).unwrap().as_str() == "seed" {
+                if let Ok(v) = caps.get(2).unwrap().as_str().parse() {
+                    seed = v;
+                }
+            }
+        }
+        schedule.holes = Rng::new(seed);
+        schedule.seed = seed;
+        Ok(schedule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// The seed of the random hole columns, if any garbage uses them. Results depend on it, so it
+    /// belongs in their cache key.
+    pub fn hole_seed(&self) -> Option<u64> {
+        if self.is_empty() || self.pattern.is_some() {
+            None
+        } else {
+            Some(self.seed)
+        }
+    }
+
+    /// Lines still to come once `placed` pieces are down and the garbage due then has risen.
+    pub fn incoming(&self, placed: usize) -> u32 {
+        self.batches.iter().filter(|b| b.after > placed).map(|b| b.lines).sum()
+    }
+
+    /// The lines shown in the meter on each page of an analysis fumen. Page `p` shows the field
+    /// before the `p`th placement, and a final summary page follows the placements.
+    pub fn meter(&self, pages: usize) -> Vec<u32> {
+        (0..pages).map(|p| match p {
+            0 => self.incoming(0) + self.rising(0),
+            p if p + 1 == pages => 0,
+            p => self.incoming(p - 1)
+        }).collect()
+    }
+
+    fn rising(&self, placed: usize) -> u32 {
+        self.batches.iter().filter(|b| b.after == placed).map(|b| b.lines).sum()
+    }
+
+    /// Raises the garbage due once `placed` pieces are down, returning the rows that rose from the
+    /// bottom up so that they can be added to the fumen with [`insert`].
+    pub fn raise(&mut self, placed: usize, board: &mut Board) -> Vec<[CellColor; 10]> {
+        let due: Vec<_> = self.batches.iter()
+            .filter(|b| b.after == placed)
+            .map(|b| b.lines as usize)
+            .collect();
+        let mut rows = vec![];
+        for lines in due {
+            let row = match self.pattern {
+                Some(row) => row,
+                None => {
+                    let mut row = [CellColor::Grey; 10];
+                    row[self.holes.below(10)] = CellColor::Empty;
+                    row
+                }
+            };
+            rows.splice(0..0, std::iter::repeat(row).take(lines));
+        }
+        if !rows.is_empty() {
+            let mut field = board.get_field();
+            for y in (rows.len()..40).rev() {
+                field[y] = field[y - rows.len()];
+            }
+            for (y, row) in rows.iter().enumerate() {
+                for x in 0..10 {
+                    field[y][x] = row[x] != CellColor::Empty;
+                }
+            }
+            board.set_field(field);
+        }
+        rows
+    }
+}
+
+/// Pushes `field` up by the garbage `rows`, given from the bottom up.
+pub fn insert(field: &mut [[CellColor; 10]; 23], rows: &[[CellColor; 10]]) {
+    let lines = rows.len().min(23);
+    for y in (lines..23).rev() {
+        field[y] = field[y - lines];
+    }
+    field[..lines].copy_from_slice(&rows[..lines]);
+}
+
+/// Draws a meter of `lines` incoming garbage in the `METER_WIDTH` pixels from `left`, which sit
+/// to the left of a field.
+pub fn draw_meter(canvas: &mut Canvas, left: usize, lines: u32) {
+    let height = canvas.height();
+    canvas.fill_rect(left, 0, METER_WIDTH, height, render::BACKGROUND);
+    let meter = (lines as usize * BLOCK_SIZE).min(height);
+    canvas.fill_rect(left, height - meter, METER_WIDTH - 2, meter, render::RED);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn garbage_row() -> [CellColor; 10] {
+        let mut row = [CellColor::Grey; 10];
+        row[0] = CellColor::Empty;
+        row
+    }
+
+    #[test]
+    fn meter() {
+        let schedule = Schedule::parse("garbage=3@2,4@5", &garbage_row()).unwrap();
+        assert_eq!(schedule.incoming(0), 7);
+        // The first page, six placements and the summary.
+        assert_eq!(schedule.meter(8), [7, 7, 7, 4, 4, 4, 0, 0]);
+    }
+
+    #[test]
+    fn raise() {
+        let mut schedule = Schedule::parse("garbage=3@2,4@5", &garbage_row()).unwrap();
+        let mut board = Board::new();
+        let mut field = board.get_field();
+        field[0][9] = true;
+        board.set_field(field);
+
+        assert!(schedule.raise(0, &mut board).is_empty());
+        assert!(schedule.raise(1, &mut board).is_empty());
+        assert_eq!(schedule.raise(2, &mut board), vec![garbage_row(); 3]);
+        let field = board.get_field();
+        for y in 0..3 {
+            assert_eq!(field[y], [false, true, true, true, true, true, true, true, true, true]);
+        }
+        assert!(field[3][9]);
+        assert_eq!(schedule.raise(5, &mut board).len(), 4);
+        assert!(board.get_field()[7][9]);
+    }
+
+    #[test]
+    fn random_holes() {
+        let invalid = Schedule::parse("garbage=0@2", &[CellColor::Empty; 10]);
+        assert!(invalid.is_err());
+        let schedule = Schedule::parse("garbage=3@2 seed=5", &[CellColor::Empty; 10]).unwrap();
+        assert_eq!(schedule.hole_seed(), Some(5));
+        let schedule = Schedule::parse("garbage=3@2", &garbage_row()).unwrap();
+        assert_eq!(schedule.hole_seed(), None);
+    }
+}
@@ -0,0 +1,392 @@
+use fumen::{ CellColor, Fumen };
+use libtetris::{ Board, FallingPiece, Piece };
+use serde::Deserialize;
+
+use super::finesse::Input;
+use super::lock_colors;
+use super::replay::MAX_REPLAY_PIECES;
+use super::rotation::{ rotated, RotationSystem };
+
+/// Fetches replay data by id. The bot uses [`HttpResolver`]; anything else can stand in for it.
+#[serenity::async_trait]
+pub trait Resolver {
+    async fn fetch(&self, id: &str) -> Option<String>;
+}
+
+pub struct HttpResolver;
+
+#[serenity::async_trait]
+impl Resolver for HttpResolver {
+    async fn fetch(&self, id: &str) -> Option<String> {
+        let url = format!("https://jstris.jezevec10.com/replay/data?id={}&type=0", id);
+        let response = reqwest::get(&url).await.ok()?;
+        if !response.status().is_success() {
+            return None
+        }
+        response.text().await.ok()
+    }
+}
+
+/// Exported replay data: the game settings and the compressed list of actions.
+#[derive(Deserialize)]
+pub struct ReplayData {
+    c: Config,
+    d: String,
+}
+
+#[derive(Deserialize)]
+struct Config {
+    seed: String,
+}
+
+/// Finds pasted replay data in a message, without using the network.
+pub fn find_data(text: &str) -> Option<ReplayData> {
+    let start = text.find("{\"c\"")?;
+    let end = text.rfind('}')?;
+    serde_json::from_str(text.get(start..=end)?).ok()
+}
+
+/// Fetches the data of the replay with the given id.
+pub async fn fetch(resolver: &(dyn Resolver + Sync), id: &str) -> Option<ReplayData> {
+    serde_json::from_str(&resolver.fetch(id).await?).ok()
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Action {
+    Left,
+    Right,
+    DasLeft,
+    DasRight,
+    Ccw,
+    Cw,
+    Flip,
+    HardDrop,
+    /// Starts or stops soft dropping.
+    SoftDrop,
+    Gravity,
+    Hold,
+    /// Moves the piece to the wall in the direction held, with an ARR of 0.
+    ArrMove,
+    Other,
+}
+
+impl Action {
+    fn from_code(code: u8) -> Action {
+        match code {
+            0 => Action::Left,
+            1 => Action::Right,
+            2 => Action::DasLeft,
+            3 => Action::DasRight,
+            4 => Action::Ccw,
+            5 => Action::Cw,
+            6 => Action::Flip,
+            7 => Action::HardDrop,
+            8 => Action::SoftDrop,
+            9 => Action::Gravity,
+            10 => Action::Hold,
+            14 => Action::ArrMove,
+            // Garbage, the red bar and auxiliary data don't move the piece.
+            _ => Action::Other
+        }
+    }
+}
+
+/// Rebuilds the placements of a replay, returning a fumen with a page for each one.
+///
+/// The actions decompress to four code units each, one byte per unit, holding a big-endian 32-bit
+/// word: a 20-bit timestamp in milliseconds, the 4-bit action code, then a byte of data such as
+/// the hole column of garbage. Pieces come from Jstris' 7-bag seeded with the replay's seed. Soft
+/// drop is treated as instant, since only its start and end are recorded. Garbage isn't replayed,
+/// so games with incoming garbage drift after the first garbage rises.
+pub fn decode(data: &ReplayData) -> Result<Fumen, String> {
+    let actions = lz_decompress(&data.d).ok_or("the replay data is corrupt")?;
+    let mut bag = Bag::new(&data.c.seed);
+    let mut board = Board::new();
+    let mut colors = [[CellColor::Empty; 10]; 40];
+    let mut piece = spawn(bag.next(), &board)?;
+    let mut hold = None;
+    let mut held = false;
+    let mut soft_drop = false;
+    // The direction moved last, which ARR moves continue in.
+    let mut direction = Input::DasLeft;
+    let mut fumen = Fumen::default();
+
+    for chunk in actions.chunks_exact(4) {
+        match Action::from_code(chunk[2] as u8 & 0xF) {
+            Action::Left => {
+                direction = Input::DasLeft;
+                piece.shift(&board, -1, 0);
+            }
+            Action::Right => {
+                direction = Input::DasRight;
+                piece.shift(&board, 1, 0);
+            }
+            Action::DasLeft => {
+                direction = Input::DasLeft;
+                direction.apply(&mut piece, &board, RotationSystem::Srs);
+            }
+            Action::DasRight => {
+                direction = Input::DasRight;
+                direction.apply(&mut piece, &board, RotationSystem::Srs);
+            }
+            Action::ArrMove => { direction.apply(&mut piece, &board, RotationSystem::Srs); }
+            Action::Ccw => { Input::Ccw.apply(&mut piece, &board, RotationSystem::Srs); }
+            Action::Cw => { Input::Cw.apply(&mut piece, &board, RotationSystem::Srs); }
+            Action::Flip => {
+                // Jstris turns 180 degrees in place, without kicks.
+                let mut flipped = piece;
+                flipped.kind.1 = rotated(piece.kind.1, 2);
+                if !board.obstructed(&flipped) {
+                    piece = flipped;
+                }
+            }
+            Action::SoftDrop => soft_drop = !soft_drop,
+            Action::Gravity => { piece.shift(&board, 0, -1); }
+            Action::Hold if !held => {
+                held = true;
+                let next = match hold.replace(piece.kind.0) {
+                    Some(swapped) => swapped,
+                    None => bag.next()
+                };
+                piece = spawn(next, &board)?;
+            }
+            Action::HardDrop => {
+                piece.sonic_drop(&board);
+                let page = fumen.add_page();
+                page.field.copy_from_slice(&colors[..23]);
+                page.piece = Some(super::to_fumen(piece));
+
+                let lock = board.lock_piece(piece);
+                lock_colors(&mut colors, piece, &lock.cleared_lines);
+                held = false;
+                if lock.locked_out || fumen.pages.len() >= MAX_REPLAY_PIECES {
+                    break
+                }
+                piece = match spawn(bag.next(), &board) {
+                    Ok(piece) => piece,
+                    Err(_) => break
+                };
+            }
+            Action::Hold | Action::Other => {}
+        }
+        if soft_drop {
+            piece.sonic_drop(&board);
+        }
+    }
+    if fumen.pages.is_empty() {
+        return Err("the replay doesn't place any pieces".to_owned())
+    }
+    Ok(fumen)
+}
+
+fn spawn(piece: Piece, board: &Board) -> Result<FallingPiece, String> {
+    RotationSystem::Srs.spawn(piece, board).ok_or_else(|| "a piece couldn't spawn".to_owned())
+}
+
+/// Jstris' 7-bag, drawing each piece of the bag at random with Alea seeded by the replay's seed.
+struct Bag {
+    rng: Alea,
+    remaining: Vec<Piece>,
+}
+
+impl Bag {
+    fn new(seed: &str) -> Bag {
+        Bag { rng: Alea::new(seed), remaining: vec![] }
+    }
+
+    fn next(&mut self) -> Piece {
+        if self.remaining.is_empty() {
+            let mut bag = vec![
+                Piece::I, Piece::O, Piece::T, Piece::L, Piece::J, Piece::S, Piece::Z
+            ];
+            while !bag.is_empty() {
+                let index = (self.rng.next() * bag.len() as f64) as usize;
+                self.remaining.push(bag.remove(index));
+            }
+            self.remaining.reverse();
+        }
+        self.remaining.pop().unwrap()
+    }
+}
+
+/// Johannes Baagøe's Alea generator, which Jstris seeds its randomizer with.
+struct Alea {
+    s0: f64,
+    s1: f64,
+    s2: f64,
+    c: f64,
+}
+
+impl Alea {
+    fn new(seed: &str) -> Alea {
+        let mut mash = Mash(0xefc8249d as f64);
+        let mut alea = Alea { s0: mash.mash(" "), s1: mash.mash(" "), s2: mash.mash(" "), c: 1.0 };
+        alea.s0 -= mash.mash(seed);
+        if alea.s0 < 0.0 {
+            alea.s0 += 1.0;
+        }
+        alea.s1 -= mash.mash(seed);
+        if alea.s1 < 0.0 {
+            alea.s1 += 1.0;
+        }
+        alea.s2 -= mash.mash(seed);
+        if alea.s2 < 0.0 {
+            alea.s2 += 1.0;
+        }
+        alea
+    }
+
+    fn next(&mut self) -> f64 {
+        let t = 2091639.0 * self.s0 + self.c * 2.3283064365386963e-10;
+        self.s0 = self.s1;
+        self.s1 = self.s2;
+        self.c = t.floor();
+        self.s2 = t - self.c;
+        self.s2
+    }
+}
+
+struct Mash(f64);
+
+impl Mash {
+    fn mash(&mut self, data: &str) -> f64 {
+        let mut n = self.0;
+        for unit in data.encode_utf16() {
+            n += unit as f64;
+            let mut h = 0.02519603282416938 * n;
+            n = (h as u64 & 0xFFFF_FFFF) as f64;
+            h -= n;
+            h *= n;
+            n = (h as u64 & 0xFFFF_FFFF) as f64;
+            h -= n;
+            n += h * 4294967296.0;
+        }
+        self.0 = n;
+        (n as u64 & 0xFFFF_FFFF) as f64 * 2.3283064365386963e-10
+    }
+}
+
+const URI_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+-$";
+
+/// LZString's `decompressFromEncodedURIComponent`, returning the decompressed UTF-16 code units.
+fn lz_decompress(input: &str) -> Option<Vec<u16>> {
+    let values = input.bytes()
+        .map(|b| if b == b' ' { b'+' } else { b })
+        .map(|b| URI_ALPHABET.iter().position(|&c| c == b).map(|v| v as u32))
+        .collect::<Option<Vec<_>>>()?;
+    let mut bits = Bits { values: &values, index: 0, position: 32, reset: 32 };
+
+    let mut dictionary: Vec<Vec<u16>> = vec![vec![], vec![], vec![]];
+    let mut enlarge_in = 4u32;
+    let mut num_bits = 3;
+    let c = match bits.read(2)? {
+        0 => bits.read(8)? as u16,
+        1 => bits.read(16)? as u16,
+        _ => return Some(vec![])
+    };
+    dictionary.push(vec![c]);
+    let mut w = vec![c];
+    let mut result = vec![c];
+    loop {
+        let mut code = bits.read(num_bits)? as usize;
+        match code {
+            0 | 1 => {
+                let unit = bits.read(if code == 0 { 8 } else { 16 })? as u16;
+                dictionary.push(vec![unit]);
+                code = dictionary.len() - 1;
+                enlarge_in -= 1;
+            }
+            2 => return Some(result),
+            _ => {}
+        }
+        if enlarge_in == 0 {
+            enlarge_in = 1 << num_bits;
+            num_bits += 1;
+        }
+        let entry = if code < dictionary.len() {
+            dictionary[code].clone()
+        } else if code == dictionary.len() {
+            let mut entry = w.clone();
+            entry.push(w[0]);
+            entry
+        } else {
+            return None
+        };
+        result.extend_from_slice(&entry);
+        let mut added = w;
+        added.push(entry[0]);
+        dictionary.push(added);
+        enlarge_in -= 1;
+        w = entry;
+        if enlarge_in == 0 {
+            enlarge_in = 1 << num_bits;
+            num_bits += 1;
+        }
+    }
+}
+
+struct Bits<'a> {
+    values: &'a [u32],
+    index: usize,
+    position: u32,
+    reset: u32,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u32) -> Option<u32> {
+        let mut bits = 0;
+        for power in 0..count {
+            let value = *self.values.get(self.index)?;
+            if value & self.position != 0 {
+                bits |= 1 << power;
+            }
+            self.position >>= 1;
+            if self.position == 0 {
+                self.position = self.reset;
+                self.index += 1;
+            }
+        }
+        Some(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use libtetris::{ PieceState, RotationState };
+
+    /// Serves replays from memory instead of Jstris.
+    struct StubResolver(HashMap<&'static str, &'static str>);
+
+    #[serenity::async_trait]
+    impl Resolver for StubResolver {
+        async fn fetch(&self, id: &str) -> Option<String> {
+            self.0.get(id).map(|&data| data.to_owned())
+        }
+    }
+
+    #[tokio::test]
+    async fn decode_replay() {
+        let mut replays = HashMap::new();
+        replays.insert("1", include_str!("testdata/jstris-replay.json"));
+        let resolver = StubResolver(replays);
+        assert!(fetch(&resolver, "2").await.is_none());
+        let fumen = decode(&fetch(&resolver, "1").await.unwrap()).unwrap();
+        // The red bar rising to 9 lines between the first two pieces doesn't move anything.
+        let pieces: Vec<_> = fumen.pages.iter()
+            .filter_map(|page| page.piece)
+            .map(super::super::to_libtetris)
+            .map(|p| (p.kind, p.x, p.y))
+            .collect();
+        assert_eq!(pieces, [
+            (PieceState(Piece::I, RotationState::North), 4, 0),
+            // DAS left.
+            (PieceState(Piece::J, RotationState::North), 1, 0),
+            // Tapped right, ARR moved to the wall, then soft dropped so the left tap is blocked.
+            (PieceState(Piece::S, RotationState::North), 8, 0),
+            // Soft drop was released, so the Z moves left before dropping.
+            (PieceState(Piece::Z, RotationState::North), 1, 1),
+        ]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::{ BTreeMap, HashMap };
+
+use once_cell::sync::Lazy;
+use serde::{ Deserialize, Serialize };
+use serenity::model::id::{ GuildId, UserId };
+use tokio::sync::Mutex;
+
+const LIBRARY_FILE: &str = "saved-fumens.json";
+
+/// Fumens saved with `-save`, by guild and then by name.
+static LIBRARY: Lazy<Mutex<HashMap<GuildId, BTreeMap<String, SavedFumen>>>> =
+    Lazy::new(|| match std::fs::File::open(LIBRARY_FILE) {
+        Ok(f) => {
+            let reader = std::io::BufReader::new(f);
+            let map = serde_json::from_reader(reader).unwrap_or_else(|_| HashMap::new());
+            Mutex::new(map)
+        }
+        Err(_) => Mutex::default(),
+    });
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedFumen {
+    /// The encoded fumen followed by its render options, e.g. `v115@...#speed=2`.
+    pub data: String,
+    pub tags: Vec<String>,
+    pub author: UserId,
+}
+
+impl SavedFumen {
+    /// Whether `query` is one of the tags (when it starts with `#`) or part of `name`.
+    fn matches(&self, name: &str, query: &str) -> bool {
+        match query.strip_prefix('#') {
+            Some(tag) => self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            None => name.contains(&query.to_lowercase())
+        }
+    }
+}
+
+pub enum Error {
+    Exists,
+    NotFound,
+    NotAuthor,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::Exists => write!(f, "There is already a fumen saved under that name."),
+            Error::NotFound => write!(f, "There is no fumen saved under that name."),
+            Error::NotAuthor => write!(f, "Only the person who saved that fumen can change it."),
+        }
+    }
+}
+
+pub async fn save(guild: GuildId, name: &str, fumen: SavedFumen) -> Result<(), Error> {
+    let mut library = LIBRARY.lock().await;
+    let saved = library.entry(guild).or_default();
+    let name = name.to_lowercase();
+    if saved.contains_key(&name) {
+        return Err(Error::Exists)
+    }
+    saved.insert(name, fumen);
+    write(&library);
+    Ok(())
+}
+
+pub async fn get(guild: GuildId, name: &str) -> Option<SavedFumen> {
+    let library = LIBRARY.lock().await;
+    library.get(&guild)?.get(&name.to_lowercase()).cloned()
+}
+
+/// Names and tags of the saved fumens matching every word of `query`, in name order.
+pub async fn search(guild: GuildId, query: &str) -> Vec<(String, Vec<String>)> {
+    let library = LIBRARY.lock().await;
+    let saved = match library.get(&guild) {
+        Some(saved) => saved,
+        None => return vec![]
+    };
+    saved.iter()
+        .filter(|(name, fumen)| query.split_whitespace().all(|q| fumen.matches(name, q)))
+        .map(|(name, fumen)| (name.clone(), fumen.tags.clone()))
+        .collect()
+}
+
+pub async fn rename(guild: GuildId, user: UserId, from: &str, to: &str) -> Result<(), Error> {
+    let mut library = LIBRARY.lock().await;
+    let saved = library.entry(guild).or_default();
+    let (from, to) = (from.to_lowercase(), to.to_lowercase());
+    match saved.get(&from) {
+        None => return Err(Error::NotFound),
+        Some(fumen) if fumen.author != user => return Err(Error::NotAuthor),
+        Some(_) if saved.contains_key(&to) => return Err(Error::Exists),
+        Some(_) => {}
+    }
+    let fumen = saved.remove(&from).unwrap();
+    saved.insert(to, fumen);
+    write(&library);
+    Ok(())
+}
+
+pub async fn delete(guild: GuildId, user: UserId, name: &str) -> Result<(), Error> {
+    let mut library = LIBRARY.lock().await;
+    let saved = library.entry(guild).or_default();
+    let name = name.to_lowercase();
+    match saved.get(&name) {
+        None => return Err(Error::NotFound),
+        Some(fumen) if fumen.author != user => return Err(Error::NotAuthor),
+        Some(_) => {}
+    }
+    saved.remove(&name);
+    write(&library);
+    Ok(())
+}
+
+fn write(library: &HashMap<GuildId, BTreeMap<String, SavedFumen>>) {
+    let f = std::fs::File::create(LIBRARY_FILE).unwrap();
+    let writer = std::io::BufWriter::new(f);
+    serde_json::to_writer(writer, library).unwrap();
+}
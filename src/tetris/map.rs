@@ -0,0 +1,140 @@
+use fumen::{ CellColor, Fumen };
+use libtetris::Piece;
+
+use super::{ from_char, piece_color, to_char };
+
+/// Rows in a TETR.IO map board: the 20 visible rows and the 20 above them.
+const MAP_ROWS: usize = 40;
+
+/// A TETR.IO custom game map: `board?bag?hold`. The board lists the cells from the top left, one
+/// character each, `_` for empty, `#` for garbage and the lowercase piece letter otherwise.
+pub struct Map {
+    pub field: [[CellColor; 10]; 23],
+    pub queue: Vec<Piece>,
+    pub hold: Option<Piece>,
+}
+
+impl Map {
+    pub fn parse(text: &str) -> Result<Map, String> {
+        let mut parts = text.trim().split('?');
+        let board = parts.next().unwrap_or("");
+        let queue = parts.next().unwrap_or("");
+        let hold = parts.next().unwrap_or("");
+        if parts.next().is_some() {
+            return Err("A map has at most three parts: `board?bag?hold`.".to_owned())
+        }
+
+        let cells = board.chars().map(|c| match c {
+            '_' => Ok(CellColor::Empty),
+            '#' | '@' => Ok(CellColor::Grey),
+            c => from_char(c.to_ascii_uppercase())
+                .map(piece_color)
+                .ok_or_else(|| format!("`{}` is not a board cell.", c))
+        }).collect::<Result<Vec<_>, _>>()?;
+        if cells.is_empty() || cells.len() % 10 != 0 {
+            return Err("The board should be 10 cells wide.".to_owned())
+        }
+        let mut field = [[CellColor::Empty; 10]; 23];
+        for (y, row) in cells.chunks(10).rev().enumerate() {
+            if y >= 23 {
+                if row.iter().any(|&c| c != CellColor::Empty) {
+                    return Err("The board is too tall to fit in a fumen.".to_owned())
+                }
+                continue
+            }
+            field[y].copy_from_slice(row);
+        }
+
+        Ok(Map { field, queue: parse_pieces(queue)?, hold: parse_pieces(hold)?.first().copied() })
+    }
+
+    /// The map as a one page fumen, with the queue in a `#Q=` comment when there is one.
+    pub fn fumen(&self) -> Fumen {
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.field = self.field;
+        if let Some((&current, next)) = self.queue.split_first() {
+            page.comment = Some(format!(
+                "#Q=[{}]({}){}",
+                self.hold.map(|p| to_char(p).to_string()).unwrap_or_default(),
+                to_char(current),
+                next.iter().map(|&p| to_char(p)).collect::<String>()
+            ));
+        }
+        fumen
+    }
+
+    pub fn encode(&self) -> String {
+        let mut board = "_".repeat(10 * (MAP_ROWS - 23));
+        for row in self.field.iter().rev() {
+            board.extend(row.iter().map(|&c| cell_char(c)));
+        }
+        format!("{}?{}?{}", board, letters(&self.queue), letters(&self.hold))
+    }
+}
+
+fn letters<'a>(pieces: impl IntoIterator<Item=&'a Piece>) -> String {
+    pieces.into_iter().map(|&p| to_char(p).to_ascii_lowercase()).collect()
+}
+
+fn parse_pieces(text: &str) -> Result<Vec<Piece>, String> {
+    text.chars()
+        .map(|c| {
+            from_char(c.to_ascii_uppercase()).ok_or_else(|| format!("`{}` is not a piece.", c))
+        })
+        .collect()
+}
+
+fn cell_char(color: CellColor) -> char {
+    match color {
+        CellColor::Empty => '_',
+        CellColor::Grey => '#',
+        CellColor::I => 'i',
+        CellColor::O => 'o',
+        CellColor::T => 't',
+        CellColor::L => 'l',
+        CellColor::J => 'j',
+        CellColor::S => 's',
+        CellColor::Z => 'z',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let map = Map::parse("ttt_______#########_?tsz?i").unwrap();
+        assert_eq!(map.field[0][..9], [CellColor::Grey; 9]);
+        assert_eq!(map.field[0][9], CellColor::Empty);
+        assert_eq!(map.field[1][..4], [CellColor::T, CellColor::T, CellColor::T, CellColor::Empty]);
+        assert_eq!(map.queue, [Piece::T, Piece::S, Piece::Z]);
+        assert_eq!(map.hold, Some(Piece::I));
+
+        let text = map.encode();
+        assert_eq!(text.len(), 10 * MAP_ROWS + "?tsz?i".len());
+        assert!(text.ends_with("ttt_______#########_?tsz?i"));
+        let again = Map::parse(&text).unwrap();
+        assert_eq!(again.field, map.field);
+        assert_eq!(again.queue, map.queue);
+        assert_eq!(again.hold, map.hold);
+    }
+
+    #[test]
+    fn fumen_comment() {
+        let fumen = Map::parse("ttt_______#########_?tsz?i").unwrap().fumen();
+        assert_eq!(fumen.pages.len(), 1);
+        assert_eq!(fumen.pages[0].comment.as_deref(), Some("#Q=[I](T)SZ"));
+        let fumen = Map::parse("#########_").unwrap().fumen();
+        assert_eq!(fumen.pages[0].comment, None);
+    }
+
+    #[test]
+    fn invalid() {
+        let too_tall = format!("#########_{}", "_".repeat(230));
+        for text in ["", "#########", "ttt_______?x", "ttt_____k_", "a?b?c?d", too_tall.as_str()] {
+            assert!(Map::parse(text).is_err(), "{} parsed", text);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+
+use fumen::CellColor;
+use libtetris::{ Board, FallingPiece, Piece };
+use once_cell::sync::Lazy;
+use serenity::model::id::{ MessageId, UserId };
+use tokio::sync::Mutex;
+
+use super::finesse::Input;
+use super::{ lock_colors, piece_color };
+use super::queue::queue_string;
+use super::render::{ self, Canvas, BLOCK_SIZE };
+use super::rotation::RotationSystem;
+
+/// Running `-play` sessions by the message showing their status and controls. Each session has
+/// its own lock, so that updating one doesn't hold up the others.
+pub static SESSIONS: Lazy<Mutex<HashMap<MessageId, Arc<Mutex<Session>>>>> =
+    Lazy::new(Mutex::default);
+
+/// Sessions that haven't seen an input for this long are dropped when a new one starts.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// The reactions added to the board message and what they do. Both adding and removing a
+/// reaction count as a press, so players don't need to remove their reaction before the next one.
+pub const CONTROLS: &[(&str, Action)] = &[
+    ("⬅️", Action::Input(Input::Left)),
+    ("➡️", Action::Input(Input::Right)),
+    ("↩️", Action::Input(Input::Ccw)),
+    ("↪️", Action::Input(Input::Cw)),
+    ("🔃", Action::Input(Input::Flip)),
+    ("🔽", Action::Input(Input::SoftDrop)),
+    ("⏬", Action::Input(Input::HardDrop)),
+    ("🇭", Action::Hold),
+];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Input(Input),
+    Hold,
+}
+
+pub fn action(emoji: &str) -> Option<Action> {
+    CONTROLS.iter().find(|&&(e, _)| e == emoji).map(|&(_, action)| action)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Goal {
+    /// Play until the queue runs out.
+    Survive,
+    PerfectClear,
+    Lines(u32),
+}
+
+impl Goal {
+    pub fn parse(text: &str) -> Option<Goal> {
+        match text {
+            "none" => Some(Goal::Survive),
+            "pc" => Some(Goal::PerfectClear),
+            n => n.parse().ok().filter(|&n| n > 0).map(Goal::Lines)
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Solved,
+    QueueEmpty,
+    ToppedOut,
+}
+
+pub struct Session {
+    pub user: UserId,
+    pub last_input: Instant,
+    board: Board,
+    colors: [[CellColor; 10]; 40],
+    piece: Option<FallingPiece>,
+    held: bool,
+    rs: RotationSystem,
+    goal: Goal,
+    lines: u32,
+    pieces: u32,
+    outcome: Option<Outcome>,
+    /// The message showing the board image, which is replaced after every input.
+    pub board_message: Option<MessageId>,
+}
+
+impl Session {
+    /// Starts a session on `board`, whose queue holds the pieces after `current`.
+    pub fn new(
+        user: UserId, board: Board, field: &[[CellColor; 10]; 23], current: Piece,
+        rs: RotationSystem, goal: Goal
+    ) -> Session {
+        let mut colors = [[CellColor::Empty; 10]; 40];
+        colors[..23].copy_from_slice(field);
+        let piece = rs.spawn(current, &board);
+        Session {
+            user,
+            last_input: Instant::now(),
+            board,
+            colors,
+            piece,
+            held: false,
+            rs,
+            goal,
+            lines: 0,
+            pieces: 0,
+            outcome: if piece.is_none() { Some(Outcome::ToppedOut) } else { None },
+            board_message: None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    /// Applies an action, returning whether anything changed.
+    pub fn apply(&mut self, action: Action) -> bool {
+        let mut piece = match self.piece {
+            Some(piece) if self.outcome.is_none() => piece,
+            _ => return false
+        };
+        self.last_input = Instant::now();
+        match action {
+            Action::Hold => {
+                if self.held {
+                    return false
+                }
+                self.held = true;
+                let next = match self.board.hold(piece.kind.0) {
+                    Some(held) => Some(held),
+                    None => self.board.advance_queue()
+                };
+                self.spawn(next);
+                true
+            }
+            Action::Input(Input::HardDrop) => {
+                piece.sonic_drop(&self.board);
+                self.lock(piece);
+                true
+            }
+            Action::Input(input) => {
+                let moved = input.apply(&mut piece, &self.board, self.rs);
+                self.piece = Some(piece);
+                moved
+            }
+        }
+    }
+
+    fn lock(&mut self, piece: FallingPiece) {
+        let lock = self.board.lock_piece(piece);
+        lock_colors(&mut self.colors, piece, &lock.cleared_lines);
+        self.pieces += 1;
+        self.lines += lock.cleared_lines.len() as u32;
+        self.held = false;
+
+        let solved = match self.goal {
+            Goal::Survive => false,
+            Goal::PerfectClear => lock.perfect_clear,
+            Goal::Lines(n) => self.lines >= n
+        };
+        if lock.locked_out {
+            self.outcome = Some(Outcome::ToppedOut);
+            self.piece = None;
+        } else if solved {
+            self.outcome = Some(Outcome::Solved);
+            self.piece = None;
+        } else {
+            let next = self.board.advance_queue();
+            self.spawn(next);
+        }
+    }
+
+    fn spawn(&mut self, next: Option<Piece>) {
+        self.piece = match next {
+            Some(next) => self.rs.spawn(next, &self.board),
+            None => {
+                self.outcome = Some(Outcome::QueueEmpty);
+                return
+            }
+        };
+        if self.piece.is_none() {
+            self.outcome = Some(Outcome::ToppedOut);
+        }
+    }
+
+    /// The hold, queue and line count, and the result once the session is over.
+    pub fn status(&self) -> String {
+        let hold = self.board.hold_piece.map(|p| queue_string(&[p])).unwrap_or_default();
+        let next = queue_string(&self.board.next_queue().take(5).collect::<Vec<_>>());
+        let mut text = format!(
+            "Hold: {}  Next: {}  Lines: {}",
+            if hold.is_empty() { "-" } else { &hold },
+            if next.is_empty() { "-" } else { &next },
+            self.lines
+        );
+        match self.outcome {
+            Some(Outcome::Solved) => text += &format!("\nSolved in {} pieces!", self.pieces),
+            Some(Outcome::QueueEmpty) => text += "\nOut of pieces. Game over.",
+            Some(Outcome::ToppedOut) => text += "\nTopped out. Game over.",
+            None => {}
+        }
+        text
+    }
+
+    /// The board as a single frame GIF, with the current piece and its ghost outlined below it.
+    pub fn render(&self) -> Result<Vec<u8>, gif::EncodingError> {
+        let cells = self.piece.map(|piece| piece.cells().to_vec()).unwrap_or_default();
+        let top = self.colors.iter()
+            .rposition(|row| row.iter().any(|&c| c != CellColor::Empty))
+            .into_iter()
+            .chain(cells.iter().map(|&(_, y)| y as usize))
+            .max()
+            .unwrap_or(0)
+            .max(3)
+            .min(21);
+        let rows = top + 1;
+        let width = BLOCK_SIZE * 10;
+
+        let mut canvas = Canvas::new(width, rows, false);
+        canvas.draw_field(0, &self.colors, None, None);
+        if let Some(piece) = self.piece {
+            let color = piece_color(piece.kind.0) as u8;
+            let mut ghost = piece;
+            ghost.sonic_drop(&self.board);
+            for &(x, y) in &ghost.cells() {
+                canvas.outline_tile(0, x as usize, y, 2, color);
+            }
+            for &(x, y) in &cells {
+                canvas.fill_tile(0, x as usize, y, color);
+            }
+        }
+
+        let mut gif_data = vec![];
+        let mut writer = render::encoder(&mut gif_data, width, canvas.height())?;
+        writer.write_frame(&canvas.into_frame(1.0))?;
+        drop(writer);
+        Ok(gif_data)
+    }
+}
+
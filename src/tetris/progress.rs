@@ -0,0 +1,73 @@
+use std::time::{ Duration, Instant };
+
+use serenity::prelude::*;
+use serenity::model::channel::Message;
+use serenity::http::AttachmentType;
+use serenity::model::id::ChannelId;
+
+/// Minimum time between edits, so that progress updates stay clear of Discord's rate limits.
+const EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// A status message that is posted when a long analysis starts and edited as it progresses.
+pub struct Progress {
+    message: Message,
+    last_edit: Instant,
+}
+
+impl Progress {
+    pub async fn start(
+        ctx: &Context, channel: ChannelId, text: &str
+    ) -> serenity::Result<Progress> {
+        let message = channel.say(&ctx.http, text).await?;
+        Ok(Progress { message, last_edit: Instant::now() })
+    }
+
+    /// Shows `text`, unless the message was edited too recently.
+    pub async fn update(&mut self, ctx: &Context, text: String) -> serenity::Result<()> {
+        if self.last_edit.elapsed() < EDIT_INTERVAL {
+            return Ok(())
+        }
+        self.last_edit = Instant::now();
+        self.message.edit(ctx, |m| m.content(text)).await
+    }
+
+    /// Leaves `text` in the status message as the final outcome, such as an error.
+    pub async fn fail(&mut self, ctx: &Context, text: String) -> serenity::Result<()> {
+        self.message.edit(ctx, |m| m.content(text)).await
+    }
+
+    /// Replaces the status message with the rendered result.
+    pub async fn finish(
+        self, ctx: &Context, gif: Vec<u8>, content: Option<String>
+    ) -> serenity::Result<Message> {
+        self.finish_with(ctx, gif, vec![], content).await
+    }
+
+    /// Replaces the status message with the rendered result and some further attachments.
+    pub async fn finish_with(
+        self, ctx: &Context, gif: Vec<u8>, extra: Vec<AttachmentType<'_>>, content: Option<String>
+    ) -> serenity::Result<Message> {
+        let mut files = vec![AttachmentType::Bytes {
+            data: gif.into(),
+            filename: "fumen.gif".into()
+        }];
+        files.extend(extra);
+        let sent = self.message.channel_id.send_files(&ctx.http, files, |f| match content {
+            Some(content) => f.content(content),
+            None => f
+        }).await?;
+        self.message.delete(ctx).await?;
+        Ok(sent)
+    }
+}
+
+/// Formats a node count compactly, e.g. `1.2M`.
+pub fn format_count(n: u64) -> String {
+    if n >= 1_000_000 {
+        format!("{:.1}M", n as f64 / 1_000_000.0)
+    } else if n >= 1_000 {
+        format!("{:.1}k", n as f64 / 1_000.0)
+    } else {
+        n.to_string()
+    }
+}
@@ -0,0 +1,248 @@
+use libtetris::Piece;
+
+use super::{ from_char, to_char };
+
+pub const ALL_PIECES: [Piece; 7] = [
+    Piece::I, Piece::O, Piece::T, Piece::L, Piece::J, Piece::S, Piece::Z
+];
+
+/// A solution-finder style queue pattern such as `T,*p4` or `[SZLJ]p2`.
+///
+/// A pattern is a sequence of groups. Each group draws `count` distinct pieces from `choices`,
+/// so `*` is any single piece, `*p4` is four different pieces and `*!` is a full bag.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    groups: Vec<Group>,
+}
+
+#[derive(Clone, Debug)]
+struct Group {
+    choices: Vec<Piece>,
+    count: usize,
+}
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Pattern, String> {
+        let mut groups = vec![];
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            let choices = match c {
+                ',' | ' ' => continue,
+                '*' => ALL_PIECES.to_vec(),
+                '[' => {
+                    let mut set = vec![];
+                    let mut negate = false;
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some('^') if set.is_empty() => negate = true,
+                            Some(c) => match from_char(c) {
+                                Some(p) if !set.contains(&p) => set.push(p),
+                                Some(_) => {}
+                                None => return Err(format!("`{}` is not a piece", c))
+                            }
+                            None => return Err("unclosed `[`".to_owned())
+                        }
+                    }
+                    if negate {
+                        set = ALL_PIECES.iter().copied().filter(|p| !set.contains(p)).collect();
+                    }
+                    if set.is_empty() {
+                        return Err("empty piece set".to_owned())
+                    }
+                    set
+                }
+                c => match from_char(c) {
+                    Some(p) => vec![p],
+                    None => return Err(format!("`{}` is not a piece", c))
+                }
+            };
+            let count = match chars.peek() {
+                Some('!') => {
+                    chars.next();
+                    choices.len()
+                }
+                Some('p') => {
+                    chars.next();
+                    let mut n = 0;
+                    while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                        chars.next();
+                        n = n * 10 + d as usize;
+                    }
+                    n
+                }
+                _ => 1
+            };
+            if count == 0 || count > choices.len() {
+                return Err(format!("cannot draw {} pieces from a set of {}", count, choices.len()))
+            }
+            groups.push(Group { choices, count });
+        }
+        Ok(Pattern { groups })
+    }
+
+    /// Whether the pattern describes exactly one queue.
+    pub fn is_literal(&self) -> bool {
+        self.groups.iter().all(|g| g.choices.len() == 1)
+    }
+
+    /// Number of distinct queues matching the pattern, saturating on overflow.
+    pub fn queue_count(&self) -> u64 {
+        self.groups.iter().fold(1u64, |total, g| {
+            (0..g.count).fold(total, |t, i| t.saturating_mul((g.choices.len() - i) as u64))
+        })
+    }
+
+    /// Lists every queue matching the pattern, or `None` if there are more than `limit`.
+    pub fn enumerate(&self, limit: usize) -> Option<Vec<Vec<Piece>>> {
+        if self.queue_count() > limit as u64 {
+            return None
+        }
+        let mut queues = vec![vec![]];
+        for group in &self.groups {
+            let mut next = vec![];
+            for queue in &queues {
+                extend_permutations(queue, &group.choices, group.count, &mut next);
+            }
+            queues = next;
+        }
+        Some(queues)
+    }
+
+    /// Draws a random queue matching the pattern.
+    pub fn sample(&self, rng: &mut Rng) -> Vec<Piece> {
+        let mut queue = vec![];
+        for group in &self.groups {
+            let mut bag = group.choices.clone();
+            for _ in 0..group.count {
+                queue.push(bag.swap_remove(rng.below(bag.len())));
+            }
+        }
+        queue
+    }
+}
+
+fn extend_permutations(prefix: &[Piece], choices: &[Piece], count: usize, out: &mut Vec<Vec<Piece>>) {
+    if count == 0 {
+        out.push(prefix.to_vec());
+        return
+    }
+    for (i, &p) in choices.iter().enumerate() {
+        let mut prefix = prefix.to_vec();
+        prefix.push(p);
+        let mut rest = choices.to_vec();
+        rest.remove(i);
+        extend_permutations(&prefix, &rest, count - 1, out);
+    }
+}
+
+pub fn queue_string(queue: &[Piece]) -> String {
+    queue.iter().copied().map(to_char).collect()
+}
+
+/// Small seeded generator (splitmix64) so that sampled queues can be reproduced from the seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// A fresh seed for when the user didn't provide one.
+pub fn random_seed() -> u64 {
+    chrono::Utc::now().timestamp_nanos() as u64 % 1_000_000_000
+}
+
+/// A 7-bag randomizer. Two bags made from the same seed deal the same pieces.
+pub struct Bag {
+    rng: Rng,
+    remaining: Vec<Piece>,
+}
+
+impl Bag {
+    pub fn new(seed: u64) -> Self {
+        Bag { rng: Rng::new(seed), remaining: vec![] }
+    }
+
+    pub fn next(&mut self) -> Piece {
+        if self.remaining.is_empty() {
+            self.remaining = ALL_PIECES.to_vec();
+        }
+        let index = self.rng.below(self.remaining.len());
+        self.remaining.swap_remove(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queues(pattern: &str) -> Vec<String> {
+        let pattern = Pattern::parse(pattern).unwrap();
+        pattern.enumerate(10_000).unwrap().iter().map(|q| queue_string(q)).collect()
+    }
+
+    #[test]
+    fn any_seven() {
+        let pattern = Pattern::parse("*p7").unwrap();
+        assert!(!pattern.is_literal());
+        assert_eq!(pattern.queue_count(), 5040);
+        let queues = queues("*p7");
+        assert_eq!(queues.len(), 5040);
+        for queue in &queues {
+            let mut pieces: Vec<char> = queue.chars().collect();
+            pieces.sort_unstable();
+            assert_eq!(pieces.into_iter().collect::<String>(), "IJLOSTZ");
+        }
+    }
+
+    #[test]
+    fn whole_set() {
+        assert_eq!(queues("[SZ]!"), ["SZ", "ZS"]);
+    }
+
+    #[test]
+    fn fixed_then_any_three() {
+        let queues = queues("L,*p3");
+        assert_eq!(queues.len(), 7 * 6 * 5);
+        assert!(queues.iter().all(|q| q.len() == 4 && q.starts_with('L')));
+        assert!(queues.contains(&"LLIO".to_owned()));
+    }
+
+    #[test]
+    fn literal() {
+        let pattern = Pattern::parse("TSZ").unwrap();
+        assert!(pattern.is_literal());
+        assert_eq!(queues("TSZ"), ["TSZ"]);
+    }
+
+    #[test]
+    fn invalid() {
+        for text in ["X", "[SZ", "[SX]", "[]", "[^IOTLJSZ]", "*p8", "*p0", "Sp2", "[SZ]p3"] {
+            assert!(Pattern::parse(text).is_err(), "{} parsed", text);
+        }
+    }
+
+    #[test]
+    fn samples_follow_pattern() {
+        let pattern = Pattern::parse("T,[SZ]!").unwrap();
+        let mut rng = Rng::new(1);
+        for _ in 0..20 {
+            let queue = queue_string(&pattern.sample(&mut rng));
+            assert!(queue == "TSZ" || queue == "TZS", "{}", queue);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::{ HashMap, VecDeque };
+
+use libtetris::{ Board, FallingPiece, Piece };
+
+use super::finesse::{ key, Key };
+use super::rotation::{ rotated, RotationSystem };
+use super::same_placement;
+
+/// One movement of a piece while searching for a path to a placement.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Step {
+    Left,
+    Right,
+    Cw,
+    Ccw,
+    Flip,
+    Down,
+}
+
+const STEPS: [Step; 6] = [Step::Left, Step::Right, Step::Cw, Step::Ccw, Step::Flip, Step::Down];
+
+impl Step {
+    pub fn name(self) -> &'static str {
+        match self {
+            Step::Left => "L",
+            Step::Right => "R",
+            Step::Cw => "CW",
+            Step::Ccw => "CCW",
+            Step::Flip => "180",
+            Step::Down => "down",
+        }
+    }
+
+    fn turns(self) -> Option<i32> {
+        match self {
+            Step::Cw => Some(1),
+            Step::Ccw => Some(3),
+            Step::Flip => Some(2),
+            _ => None
+        }
+    }
+
+    fn apply(self, piece: &mut FallingPiece, board: &Board, rs: RotationSystem) -> bool {
+        match self {
+            Step::Left => piece.shift(board, -1, 0),
+            Step::Right => piece.shift(board, 1, 0),
+            Step::Down => piece.shift(board, 0, -1),
+            Step::Flip if !rs.has_180() => false,
+            _ => {
+                let to = rotated(piece.kind.1, self.turns().unwrap());
+                rs.rotate(piece, board, to)
+            }
+        }
+    }
+}
+
+/// Every location a piece can get to from spawn, with the shortest way of getting there.
+pub struct Search {
+    rs: RotationSystem,
+    visited: HashMap<Key, (FallingPiece, Option<(Key, Step)>)>,
+    order: Vec<Key>,
+}
+
+/// A rotation whose kick table lists the target, but where an earlier test succeeded first.
+pub struct KickAttempt {
+    pub from: FallingPiece,
+    pub step: Step,
+    /// Every test up to and including the target, with whether it fit.
+    pub tests: Vec<(FallingPiece, bool)>,
+}
+
+impl Search {
+    /// Explores from spawn, or returns `None` if the piece can't spawn.
+    pub fn new(rs: RotationSystem, board: &Board, piece: Piece) -> Option<Search> {
+        let spawn = rs.spawn(piece, board)?;
+        let mut visited = HashMap::new();
+        let mut order = vec![key(&spawn)];
+        let mut queue = VecDeque::new();
+        visited.insert(key(&spawn), (spawn, None));
+        queue.push_back(spawn);
+        while let Some(piece) = queue.pop_front() {
+            for &step in &STEPS {
+                let mut next = piece;
+                if step.apply(&mut next, board, rs) && !visited.contains_key(&key(&next)) {
+                    visited.insert(key(&next), (next, Some((key(&piece), step))));
+                    order.push(key(&next));
+                    queue.push_back(next);
+                }
+            }
+        }
+        Some(Search { rs, visited, order })
+    }
+
+    /// The shortest path from spawn to a location filling the same cells as `target`.
+    pub fn path_to(&self, target: &FallingPiece) -> Option<Vec<(Step, FallingPiece)>> {
+        let mut current = *self.order.iter()
+            .find(|k| same_placement(self.visited[k].0, *target))?;
+        let mut path = vec![];
+        while let (piece, Some((parent, step))) = self.visited[&current] {
+            path.push((step, piece));
+            current = parent;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    pub fn spawn(&self) -> FallingPiece {
+        self.visited[&self.order[0]].0
+    }
+
+    /// Rotations into `target` that fail because an earlier kick test succeeds.
+    pub fn failed_kicks(&self, board: &Board, target: &FallingPiece) -> Vec<KickAttempt> {
+        let mut attempts = vec![];
+        for k in &self.order {
+            let from = self.visited[k].0;
+            for &step in &[Step::Cw, Step::Ccw, Step::Flip] {
+                let to = rotated(from.kind.1, step.turns().unwrap());
+                let candidates = self.rs.kick_tests(from, to);
+                if let Some(index) = candidates.iter().position(|&c| same_placement(c, *target)) {
+                    let tests = candidates[..=index].iter()
+                        .map(|c| (*c, !board.obstructed(c)))
+                        .collect();
+                    attempts.push(KickAttempt { from, step, tests });
+                }
+            }
+        }
+        attempts
+    }
+}
+
+/// Describes a path compactly, merging repeated steps, e.g. `L x2, CW, down x3, CW`.
+pub fn describe_path(path: &[(Step, FallingPiece)]) -> String {
+    let mut parts: Vec<(Step, usize)> = vec![];
+    for &(step, _) in path {
+        match parts.last_mut() {
+            Some((last, count)) if *last == step => *count += 1,
+            _ => parts.push((step, 1))
+        }
+    }
+    parts.iter().map(|&(step, count)| match count {
+        1 => step.name().to_owned(),
+        n => format!("{} x{}", step.name(), n)
+    }).collect::<Vec<_>>().join(", ")
+}
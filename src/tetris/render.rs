@@ -0,0 +1,203 @@
+use fumen::CellColor;
+
+use super::to_libtetris;
+
+pub const GLOBAL_PALETTE: &'static [u8] = &[
+    0x40, 0x40, 0x40,
+    0x00, 0xFF, 0xFF,
+    0xFF, 0x80, 0x00,
+    0xFF, 0xFF, 0x00,
+    0xFF, 0x00, 0x00,
+    0x80, 0x00, 0xFF,
+    0x00, 0x20, 0xFF,
+    0x00, 0xFF, 0x00,
+    0x80, 0x80, 0x80,
+    0x10, 0x10, 0x10,
+    0xFF, 0xFF, 0xFF
+];
+const JSTRIS_PALETTE: &[u8] = &[
+    0x3A, 0x3A, 0x3A,
+    0x0F, 0x9B, 0xD7,
+    0xE3, 0x5B, 0x02,
+    0xE3, 0x9F, 0x02,
+    0xD7, 0x0F, 0x37,
+    0xAF, 0x29, 0x8A,
+    0x21, 0x41, 0xC6,
+    0x59, 0xB1, 0x01,
+    0x99, 0x99, 0x99,
+    0x00, 0x00, 0x00,
+    0xFF, 0xFF, 0xFF
+];
+const LIGHT_PALETTE: &[u8] = &[
+    0xD8, 0xD8, 0xD8,
+    0x00, 0xB0, 0xE0,
+    0xF0, 0x80, 0x10,
+    0xE8, 0xC0, 0x00,
+    0xE0, 0x20, 0x30,
+    0x90, 0x30, 0xC0,
+    0x20, 0x50, 0xD0,
+    0x30, 0xB8, 0x30,
+    0x70, 0x70, 0x70,
+    0xF8, 0xF8, 0xF8,
+    0x20, 0x20, 0x20
+];
+/// The palettes renders can be drawn in, chosen with the `theme=` option.
+pub const THEMES: &[(&str, &[u8])] = &[
+    ("default", GLOBAL_PALETTE),
+    ("jstris", JSTRIS_PALETTE),
+    ("light", LIGHT_PALETTE),
+];
+pub const BLOCK_SIZE: usize = 24;
+pub const BACKGROUND: u8 = 9;
+pub const RED: u8 = CellColor::Z as u8;
+pub const WHITE: u8 = 10;
+
+/// The pixels of one frame, drawn a tile at a time.
+///
+/// Tile rows are counted up from the bottom of the field. When there is a garbage row it sits
+/// at row -1, below a thin separator.
+pub struct Canvas {
+    pub buf: Vec<u8>,
+    pub width: usize,
+    rows: usize,
+    has_garbage_row: bool,
+}
+
+impl Canvas {
+    /// `rows` counts the garbage row, if there is one.
+    pub fn new(width: usize, rows: usize, has_garbage_row: bool) -> Canvas {
+        Canvas {
+            buf: vec![0; width * pixel_height(rows, has_garbage_row)],
+            width,
+            rows,
+            has_garbage_row,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        pixel_height(self.rows, self.has_garbage_row)
+    }
+
+    pub fn fill_tile(&mut self, left: usize, x: usize, y: i32, color: u8) {
+        let garbage = self.has_garbage_row as i32;
+        let row = self.rows as i32 - y - 1 - garbage;
+        if row < 0 {
+            return
+        }
+        let tp = left + BLOCK_SIZE*x + BLOCK_SIZE*self.width*row as usize;
+        for iy in 0..BLOCK_SIZE {
+            for ix in 0..BLOCK_SIZE {
+                let i = tp + iy*self.width + ix;
+                if self.has_garbage_row && y == -1 {
+                    if iy < 2 {
+                        self.buf[i] = BACKGROUND;
+                    }
+                    self.buf[i + 2*self.width] = color;
+                } else {
+                    self.buf[i] = color;
+                }
+            }
+        }
+    }
+
+    /// Draws a border of `width` pixels just inside a tile, to mark it without hiding its colour.
+    pub fn outline_tile(&mut self, left: usize, x: usize, y: i32, width: usize, color: u8) {
+        let garbage = self.has_garbage_row as i32;
+        let row = self.rows as i32 - y - 1 - garbage;
+        if row < 0 || y < 0 {
+            return
+        }
+        let (px, py) = (left + BLOCK_SIZE*x, BLOCK_SIZE*row as usize);
+        self.fill_rect(px, py, BLOCK_SIZE, width, color);
+        self.fill_rect(px, py + BLOCK_SIZE - width, BLOCK_SIZE, width, color);
+        self.fill_rect(px, py, width, BLOCK_SIZE, color);
+        self.fill_rect(px + BLOCK_SIZE - width, py, width, BLOCK_SIZE, color);
+    }
+
+    /// Fills a rectangle given in pixels from the top left corner.
+    pub fn fill_rect(&mut self, x: usize, y: usize, w: usize, h: usize, color: u8) {
+        let height = self.height();
+        for py in y..(y + h).min(height) {
+            for px in x..(x + w).min(self.width) {
+                self.buf[py*self.width + px] = color;
+            }
+        }
+    }
+
+    pub fn draw_field(
+        &mut self, left: usize, field: &[[CellColor; 10]], garbage_row: Option<&[CellColor; 10]>,
+        piece: Option<fumen::Piece>
+    ) {
+        for y in 0..(self.rows - self.has_garbage_row as usize).min(field.len()) {
+            for x in 0..10 {
+                self.fill_tile(left, x, y as i32, field[y][x] as u8);
+            }
+        }
+        if let Some(garbage_row) = garbage_row.filter(|_| self.has_garbage_row) {
+            for x in 0..10 {
+                self.fill_tile(left, x, -1, garbage_row[x] as u8);
+            }
+        }
+        if let Some(piece) = piece {
+            let color: CellColor = piece.kind.into();
+            for &(x, y) in &to_libtetris(piece).cells() {
+                self.fill_tile(left, x as usize, y, color as u8);
+            }
+        }
+    }
+
+    pub fn draw_page(&mut self, left: usize, page: &fumen::Page) {
+        self.draw_field(left, &page.field, Some(&page.garbage_row), page.piece);
+    }
+
+    pub fn into_frame(self, speed: f64) -> gif::Frame<'static> {
+        gif::Frame {
+            delay: (50.0 / speed).round() as u16,
+            width: self.width as u16,
+            height: self.height() as u16,
+            buffer: self.buf.into(),
+            ..Default::default()
+        }
+    }
+}
+
+pub fn pixel_height(rows: usize, has_garbage_row: bool) -> usize {
+    rows*BLOCK_SIZE + 2*(has_garbage_row as usize)
+}
+
+/// Number of rows needed to show everything in `field` and `piece`.
+pub fn field_rows(field: &[[CellColor; 10]], piece: Option<fumen::Piece>) -> usize {
+    field.iter()
+        .enumerate()
+        .rfind(|(_,&r)| r != [CellColor::Empty; 10])
+        .map(|(i,_)| i)
+        .unwrap_or(0)
+        .max(piece
+            .map(to_libtetris)
+            .and_then(|p| p.cells().iter()
+                .map(|&(_,y)| y as usize)
+                .max())
+            .unwrap_or(0)
+        ) + 1
+}
+
+/// The palette of the theme called `name`, if there is one.
+pub fn theme(name: &str) -> Option<&'static [u8]> {
+    THEMES.iter()
+        .find(|&&(theme, _)| theme.eq_ignore_ascii_case(name))
+        .map(|&(_, palette)| palette)
+}
+
+pub fn encoder(
+    data: &mut Vec<u8>, width: usize, height: usize
+) -> Result<gif::Encoder<&mut Vec<u8>>, gif::EncodingError> {
+    themed_encoder(data, width, height, GLOBAL_PALETTE)
+}
+
+pub fn themed_encoder(
+    data: &mut Vec<u8>, width: usize, height: usize, palette: &[u8]
+) -> Result<gif::Encoder<&mut Vec<u8>>, gif::EncodingError> {
+    let mut writer = gif::Encoder::new(data, width as u16, height as u16, palette)?;
+    writer.set_repeat(gif::Repeat::Infinite)?;
+    Ok(writer)
+}
@@ -0,0 +1,3 @@
+/// Most placements turned into fumen pages when replaying a game, to keep renders and links a
+/// sensible size.
+pub const MAX_REPLAY_PIECES: usize = 300;
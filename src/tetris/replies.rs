@@ -0,0 +1,21 @@
+use once_cell::sync::Lazy;
+use serenity::model::id::MessageId;
+use tokio::sync::Mutex;
+
+use super::bounded::BoundedMap;
+
+/// Renders followed for edits and deletions of their source; the oldest are forgotten first.
+const MAX_REPLIES: usize = 1000;
+
+/// The render posted for each message with a fumen.
+static REPLIES: Lazy<Mutex<BoundedMap<MessageId, MessageId>>> =
+    Lazy::new(|| Mutex::new(BoundedMap::new(MAX_REPLIES)));
+
+pub async fn remember(source: MessageId, reply: MessageId) {
+    REPLIES.lock().await.insert(source, reply);
+}
+
+/// Stops following `source`, returning its render if there was one.
+pub async fn forget(source: MessageId) -> Option<MessageId> {
+    REPLIES.lock().await.remove(&source)
+}
@@ -0,0 +1,349 @@
+use libtetris::{ Board, FallingPiece, Piece, PieceState, RotationState, TspinStatus };
+
+use super::EXTRACT_OPTIONS;
+
+/// Kick tables and spawn rules for piece movement, selected with the `rs=` option.
+///
+/// Locations are always expressed in libtetris' SRS coordinates, so the other systems are
+/// described by how far their rotation boxes sit from SRS' pure rotation about the piece centre.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RotationSystem {
+    /// Guideline SRS, as implemented by libtetris.
+    Srs,
+    /// TETR.IO's SRS+: symmetric I kicks and 180 spins.
+    SrsPlus,
+    /// Arika's rotation system (TGM): bottom-aligned states with simple wall kicks.
+    Ars,
+    /// Nintendo's rotation system (NES): right-handed states without kicks.
+    Nrs,
+}
+
+impl RotationSystem {
+    pub fn parse(name: &str) -> Option<RotationSystem> {
+        Some(match &*name.to_ascii_lowercase() {
+            "srs" | "guideline" => RotationSystem::Srs,
+            "srsplus" | "srs_plus" | "srsp" | "tetrio" => RotationSystem::SrsPlus,
+            "ars" | "tgm" => RotationSystem::Ars,
+            "nrs" | "nes" => RotationSystem::Nrs,
+            _ => return None
+        })
+    }
+
+    /// Reads `rs=` from a fumen's options, defaulting to SRS.
+    pub fn from_options(options: &str) -> Result<RotationSystem, String> {
+        for caps in EXTRACT_OPTIONS.captures_iter(options) {
+            if caps.get(1).unwrap().as_str() == "rs" {
+                let value = caps.get(2).unwrap().as_str();
+                return RotationSystem::parse(value).ok_or_else(|| format!(
+                    "Unknown rotation system `{}`. Try srs, srsplus, ars or nrs.", value
+                ))
+            }
+        }
+        Ok(RotationSystem::Srs)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RotationSystem::Srs => "SRS",
+            RotationSystem::SrsPlus => "SRS+",
+            RotationSystem::Ars => "ARS",
+            RotationSystem::Nrs => "NRS",
+        }
+    }
+
+    pub fn has_180(self) -> bool {
+        self == RotationSystem::SrsPlus
+    }
+
+    pub fn spawn(self, piece: Piece, board: &Board) -> Option<FallingPiece> {
+        match self {
+            RotationSystem::Srs | RotationSystem::SrsPlus => FallingPiece::spawn(piece, board),
+            RotationSystem::Ars | RotationSystem::Nrs => {
+                // Both spawn T, J and L flat side up, entirely inside the visible field.
+                let rotation = match piece {
+                    Piece::T | Piece::J | Piece::L => RotationState::South,
+                    _ => RotationState::North
+                };
+                let mut spawned = FallingPiece {
+                    kind: PieceState(piece, rotation),
+                    x: 4,
+                    y: 0,
+                    tspin: TspinStatus::None,
+                };
+                let top = spawned.cells().iter().map(|&(_, y)| y).max().unwrap();
+                spawned.y = 19 - top;
+                if board.obstructed(&spawned) {
+                    None
+                } else {
+                    Some(spawned)
+                }
+            }
+        }
+    }
+
+    /// The locations tried, in order, when rotating `piece` into the `to` state.
+    pub fn kick_tests(self, piece: FallingPiece, to: RotationState) -> Vec<FallingPiece> {
+        let from = piece.kind.1;
+        let kind = piece.kind.0;
+        let kicks: Vec<(i32, i32)> = match self {
+            _ if from == to => vec![],
+            RotationSystem::Srs if is_180(from, to) => vec![],
+            RotationSystem::Srs => offset_kicks(kind, from, to),
+            RotationSystem::SrsPlus if is_180(from, to) => {
+                let base = base_offset(kind, from, to);
+                flip_kicks(kind, from).iter().map(|&(x, y)| (x + base.0, y + base.1)).collect()
+            }
+            RotationSystem::SrsPlus if kind == Piece::I => {
+                let base = base_offset(kind, from, to);
+                srs_plus_i_kicks(from, to).iter()
+                    .map(|&(x, y)| (x + base.0, y + base.1))
+                    .collect()
+            }
+            RotationSystem::SrsPlus => offset_kicks(kind, from, to),
+            RotationSystem::Ars | RotationSystem::Nrs if is_180(from, to) => vec![],
+            RotationSystem::Ars | RotationSystem::Nrs => {
+                let table: fn(Piece, RotationState) -> (i32, i32) = match self {
+                    RotationSystem::Ars => ars_offset,
+                    _ => nrs_offset
+                };
+                let (fx, fy) = table(kind, from);
+                let (tx, ty) = table(kind, to);
+                let base = (tx - fx, ty - fy);
+                if self == RotationSystem::Ars && kind != Piece::I {
+                    vec![base, (base.0 + 1, base.1), (base.0 - 1, base.1)]
+                } else {
+                    vec![base]
+                }
+            }
+        };
+        kicks.into_iter().map(|(dx, dy)| FallingPiece {
+            kind: PieceState(kind, to),
+            x: piece.x + dx,
+            y: piece.y + dy,
+            tspin: TspinStatus::None,
+        }).collect()
+    }
+
+    /// Rotates `piece` into the `to` state, returning whether any kick test fit.
+    pub fn rotate(self, piece: &mut FallingPiece, board: &Board, to: RotationState) -> bool {
+        if self == RotationSystem::Srs {
+            // libtetris also works out T-spin status, so prefer it when it applies.
+            if to == rotated(piece.kind.1, 1) {
+                return piece.cw(board)
+            } else if to == rotated(piece.kind.1, 3) {
+                return piece.ccw(board)
+            }
+        }
+        match self.kick_tests(*piece, to).into_iter().find(|p| !board.obstructed(p)) {
+            Some(p) => {
+                *piece = p;
+                true
+            }
+            None => false
+        }
+    }
+}
+
+/// The state reached by turning `state` clockwise `turns` times.
+pub fn rotated(state: RotationState, turns: i32) -> RotationState {
+    const ORDER: [RotationState; 4] = [
+        RotationState::North, RotationState::East, RotationState::South, RotationState::West
+    ];
+    let index = ORDER.iter().position(|&s| s == state).unwrap() as i32;
+    ORDER[(index + turns).rem_euclid(4) as usize]
+}
+
+fn is_180(from: RotationState, to: RotationState) -> bool {
+    rotated(from, 2) == to
+}
+
+fn offset_kicks(kind: Piece, from: RotationState, to: RotationState) -> Vec<(i32, i32)> {
+    srs_offsets(kind, from).iter().zip(srs_offsets(kind, to).iter())
+        .map(|(&(fx, fy), &(tx, ty))| (fx - tx, fy - ty))
+        .collect()
+}
+
+/// Converts a kick table written for the guideline's true rotation centre into libtetris
+/// coordinates, which only differ for I and O.
+fn base_offset(kind: Piece, from: RotationState, to: RotationState) -> (i32, i32) {
+    let (fx, fy) = srs_offsets(kind, from)[0];
+    let (tx, ty) = srs_offsets(kind, to)[0];
+    (fx - tx, fy - ty)
+}
+
+/// SRS offset tables; the kick for test `n` is `from[n] - to[n]`.
+fn srs_offsets(piece: Piece, state: RotationState) -> &'static [(i32, i32)] {
+    match (piece, state) {
+        (Piece::O, RotationState::North) => &[(0, 0)],
+        (Piece::O, RotationState::East) => &[(0, -1)],
+        (Piece::O, RotationState::South) => &[(-1, -1)],
+        (Piece::O, RotationState::West) => &[(-1, 0)],
+        (Piece::I, RotationState::North) => &[(0, 0), (-1, 0), (2, 0), (-1, 0), (2, 0)],
+        (Piece::I, RotationState::East) => &[(-1, 0), (0, 0), (0, 0), (0, 1), (0, -2)],
+        (Piece::I, RotationState::South) => &[(-1, 1), (1, 1), (-2, 1), (1, 0), (-2, 0)],
+        (Piece::I, RotationState::West) => &[(0, 1), (0, 1), (0, 1), (0, -1), (0, 2)],
+        (_, RotationState::North) | (_, RotationState::South) => &[(0, 0); 5],
+        (_, RotationState::East) => &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+        (_, RotationState::West) => &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+    }
+}
+
+/// TETR.IO's I kicks, in guideline true-rotation coordinates.
+fn srs_plus_i_kicks(from: RotationState, to: RotationState) -> &'static [(i32, i32)] {
+    use RotationState::*;
+    match (from, to) {
+        (North, East) => &[(0, 0), (1, 0), (-2, 0), (-2, -1), (1, 2)],
+        (East, North) => &[(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+        (East, South) => &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+        (South, East) => &[(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)],
+        (South, West) => &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+        (West, South) => &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+        (West, North) => &[(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)],
+        (North, West) => &[(0, 0), (-1, 0), (2, 0), (2, -1), (-1, 2)],
+        _ => &[(0, 0)],
+    }
+}
+
+/// TETR.IO's 180 kicks, indexed by the starting state.
+fn flip_kicks(kind: Piece, from: RotationState) -> &'static [(i32, i32)] {
+    if kind == Piece::I || kind == Piece::O {
+        return &[(0, 0)]
+    }
+    match from {
+        RotationState::North => &[(0, 0), (0, 1), (1, 1), (-1, 1), (1, 0), (-1, 0)],
+        RotationState::South => &[(0, 0), (0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)],
+        RotationState::East => &[(0, 0), (1, 0), (1, 2), (1, 1), (0, 2), (0, 1)],
+        RotationState::West => &[(0, 0), (-1, 0), (-1, 2), (-1, 1), (0, 2), (0, 1)],
+    }
+}
+
+/// Where ARS places each state relative to SRS' pure rotation: 3-wide states rest on the bottom
+/// of the box and vertical S, Z and I stay in fixed columns.
+fn ars_offset(kind: Piece, state: RotationState) -> (i32, i32) {
+    use RotationState::*;
+    match (kind, state) {
+        (Piece::T, North) | (Piece::J, North) | (Piece::L, North) => (0, -1),
+        (Piece::S, North) | (Piece::Z, North) => (0, -1),
+        (Piece::S, East) => (-1, 0),
+        (Piece::Z, West) => (1, 0),
+        (Piece::I, South) => (1, 0),
+        (Piece::I, East) => (1, 0),
+        (Piece::I, West) => (1, -1),
+        (Piece::O, East) => (0, 1),
+        (Piece::O, South) => (1, 1),
+        (Piece::O, West) => (1, 0),
+        _ => (0, 0)
+    }
+}
+
+/// Like [`ars_offset`] for NES: pure rotation for T, J and L, right-handed S, Z and I.
+fn nrs_offset(kind: Piece, state: RotationState) -> (i32, i32) {
+    use RotationState::*;
+    match (kind, state) {
+        (Piece::S, North) | (Piece::Z, North) => (0, -1),
+        (Piece::S, West) | (Piece::Z, West) => (1, 0),
+        (Piece::I, North) => (-1, 0),
+        (Piece::I, West) => (0, -1),
+        (Piece::O, East) => (0, 1),
+        (Piece::O, South) => (1, 1),
+        (Piece::O, West) => (1, 0),
+        _ => (0, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(filled: &[(usize, usize)]) -> Board {
+        let mut field = [[false; 10]; 40];
+        for &(x, y) in filled {
+            field[y][x] = true;
+        }
+        let mut board = Board::new();
+        board.set_field(field);
+        board
+    }
+
+    fn piece(kind: Piece, state: RotationState, x: i32, y: i32) -> FallingPiece {
+        FallingPiece { kind: PieceState(kind, state), x, y, tspin: TspinStatus::None }
+    }
+
+    /// Three full rows with a slot that only the fifth North to East kick reaches.
+    #[test]
+    fn srs_tst_kick() {
+        let mut filled = vec![(3, 4)];
+        for y in 0..3 {
+            for x in 0..10 {
+                if x != 3 && !(y == 1 && x == 4) {
+                    filled.push((x, y));
+                }
+            }
+        }
+        let board = board(&filled);
+        let mut t = piece(Piece::T, RotationState::North, 4, 3);
+        assert!(!board.obstructed(&t));
+
+        let tests = RotationSystem::Srs.kick_tests(t, RotationState::East);
+        let kicks: Vec<_> = tests.iter().map(|p| (p.x - t.x, p.y - t.y)).collect();
+        assert_eq!(kicks, [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]);
+
+        assert!(RotationSystem::Srs.rotate(&mut t, &board, RotationState::East));
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::East, 3, 1));
+        let mut board = board;
+        assert_eq!(board.lock_piece(t).cleared_lines.len(), 3);
+    }
+
+    /// SRS+ tries the I's North to East kicks towards the right first, where SRS goes left.
+    #[test]
+    fn srs_plus_i_kick() {
+        let i = piece(Piece::I, RotationState::North, 4, 10);
+        let tests = RotationSystem::SrsPlus.kick_tests(i, RotationState::East);
+        let srs = RotationSystem::Srs.kick_tests(i, RotationState::East);
+        assert_eq!(tests[0].x, srs[0].x);
+        assert_eq!(tests[1].x - tests[0].x, 1);
+        assert_eq!(srs[1].x - srs[0].x, -2);
+
+        // Block the first test with a cell the second doesn't use.
+        let blocker = tests[0].cells().iter()
+            .find(|c| !tests[1].cells().contains(c) && !i.cells().contains(c))
+            .copied()
+            .unwrap();
+        let board = board(&[(blocker.0 as usize, blocker.1 as usize)]);
+        let mut moved = i;
+        assert!(RotationSystem::SrsPlus.rotate(&mut moved, &board, RotationState::East));
+        assert_eq!((moved.x, moved.y), (tests[1].x, tests[1].y));
+    }
+
+    /// A T resting on the floor flips by kicking up a row, which SRS doesn't allow at all.
+    #[test]
+    fn srs_plus_180_kick() {
+        let board = board(&[]);
+        let mut t = piece(Piece::T, RotationState::North, 4, 0);
+        assert!(RotationSystem::Srs.kick_tests(t, RotationState::South).is_empty());
+        assert!(RotationSystem::SrsPlus.rotate(&mut t, &board, RotationState::South));
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::South, 4, 1));
+    }
+
+    /// A T standing against the left wall kicks one column right to lie flat.
+    #[test]
+    fn ars_wall_kick() {
+        let board = board(&[]);
+        let mut t = piece(Piece::T, RotationState::East, 0, 5);
+        assert!(RotationSystem::Ars.rotate(&mut t, &board, RotationState::North));
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::North, 1, 4));
+    }
+
+    /// NES rotation is a pure turn, with no kick away from the wall.
+    #[test]
+    fn nrs_rotation() {
+        let board = board(&[]);
+        let mut t = piece(Piece::T, RotationState::East, 4, 5);
+        assert!(RotationSystem::Nrs.rotate(&mut t, &board, RotationState::South));
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::South, 4, 5));
+
+        let mut t = piece(Piece::T, RotationState::East, 0, 5);
+        assert!(!RotationSystem::Nrs.rotate(&mut t, &board, RotationState::North));
+        assert_eq!((t.kind.1, t.x, t.y), (RotationState::East, 0, 5));
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex };
+
+use once_cell::sync::Lazy;
+use serenity::model::id::UserId;
+use tokio::sync::Notify;
+
+/// Global limit on Cold Clear searches so that concurrent `-cc` calls can't starve the gateway.
+///
+/// The limits are read from `CC_MAX_JOBS` (default 2) and `CC_MAX_JOBS_PER_USER` (default 1).
+pub static SCHEDULER: Lazy<Scheduler> = Lazy::new(|| {
+    let read = |var: &str, default| std::env::var(var).ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default);
+    Scheduler::new(read("CC_MAX_JOBS", 2).max(1), read("CC_MAX_JOBS_PER_USER", 1).max(1))
+});
+
+pub struct Scheduler {
+    state: Mutex<State>,
+    changed: Notify,
+    next_id: AtomicU64,
+    max_jobs: usize,
+    max_per_user: usize,
+}
+
+struct State {
+    running: Vec<Job>,
+    waiting: VecDeque<Job>,
+}
+
+struct Job {
+    id: u64,
+    user: UserId,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// A job's place in the scheduler. The slot is released when the ticket is dropped.
+pub struct Ticket {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+pub enum Rejected {
+    TooManyJobs(usize),
+}
+
+impl Scheduler {
+    fn new(max_jobs: usize, max_per_user: usize) -> Self {
+        Scheduler {
+            state: Mutex::new(State { running: vec![], waiting: VecDeque::new() }),
+            changed: Notify::new(),
+            next_id: AtomicU64::new(0),
+            max_jobs,
+            max_per_user,
+        }
+    }
+
+    /// Adds a job for `user` to the back of the queue.
+    pub fn enqueue(&self, user: UserId) -> Result<Ticket, Rejected> {
+        let mut state = self.state.lock().unwrap();
+        let existing = state.running.iter().chain(&state.waiting).filter(|j| j.user == user).count();
+        if existing >= self.max_per_user {
+            return Err(Rejected::TooManyJobs(self.max_per_user))
+        }
+        let job = Job {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            user,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        let ticket = Ticket { id: job.id, cancelled: job.cancelled.clone() };
+        state.waiting.push_back(job);
+        self.promote(&mut state);
+        Ok(ticket)
+    }
+
+    /// Cancels every running or queued job belonging to `user`. Returns how many were cancelled.
+    pub fn cancel(&self, user: UserId) -> usize {
+        let state = self.state.lock().unwrap();
+        let mut count = 0;
+        for job in state.running.iter().chain(&state.waiting).filter(|j| j.user == user) {
+            job.cancelled.store(true, Ordering::Relaxed);
+            count += 1;
+        }
+        drop(state);
+        self.changed.notify_waiters();
+        count
+    }
+
+    fn promote(&self, state: &mut State) {
+        let mut promoted = false;
+        while state.running.len() < self.max_jobs {
+            match state.waiting.pop_front() {
+                Some(job) => state.running.push(job),
+                None => break
+            }
+            promoted = true;
+        }
+        if promoted {
+            self.changed.notify_waiters();
+        }
+    }
+
+    fn position(&self, id: u64) -> Option<usize> {
+        let state = self.state.lock().unwrap();
+        state.waiting.iter().position(|j| j.id == id).map(|p| p + 1)
+    }
+
+    fn release(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.running.retain(|j| j.id != id);
+        state.waiting.retain(|j| j.id != id);
+        self.promote(&mut state);
+        drop(state);
+        self.changed.notify_waiters();
+    }
+}
+
+impl Ticket {
+    /// Position in the waiting queue, starting at 1, or `None` if the job may run now.
+    pub fn position(&self) -> Option<usize> {
+        SCHEDULER.position(self.id)
+    }
+
+    /// Waits until the queue changes, returning the new position like [`Ticket::position`].
+    pub async fn wait_for_change(&self) -> Option<usize> {
+        let changed = SCHEDULER.changed.notified();
+        if self.position().is_none() || self.is_cancelled() {
+            return None
+        }
+        changed.await;
+        self.position()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        SCHEDULER.release(self.id);
+    }
+}
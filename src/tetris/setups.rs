@@ -0,0 +1,197 @@
+use fumen::{ CellColor, Fumen };
+
+use super::{ from_char, piece_color };
+
+/// A named setup, drawn top row first. Letters are piece colours, `X` is garbage and `.` is empty.
+pub struct Setup {
+    pub name: &'static str,
+    aliases: &'static [&'static str],
+    rows: &'static [&'static str],
+    /// What the setup leads to, each page drawn the same way with a comment.
+    follow_ups: &'static [(&'static str, &'static [&'static str])],
+}
+
+const SETUPS: &[Setup] = &[
+    Setup {
+        name: "TKI",
+        aliases: &["tki3", "tki-3"],
+        rows: &[
+            "S.........",
+            "SS...Z....",
+            "LS..ZZ....",
+            "L...ZJJJOO",
+            "LL.IIIIJOO",
+        ],
+        follow_ups: &[
+            ("TSD", &[
+                "S.........",
+                "SS...Z....",
+                "LS..ZZ....",
+                "LTTTZJJJOO",
+                "LLTIIIIJOO",
+            ]),
+        ],
+    },
+    Setup {
+        name: "DT Cannon",
+        aliases: &["dt", "dtcannon", "dt-cannon"],
+        rows: &[
+            "T.........",
+            "TT....SS.L",
+            "T...JSSLLL",
+            "II.JJJJZOO",
+            "II.JJJZZOO",
+            "II..SSZLOO",
+            "II.SSLLLOO",
+        ],
+        follow_ups: &[
+            ("TSD", &[
+                "T.........",
+                "TT....SS.L",
+                "TTTTJSSLLL",
+                "IITJJJJZOO",
+                "II.JJJZZOO",
+                "II..SSZLOO",
+                "II.SSLLLOO",
+            ]),
+            ("Z over the slot, then TST", &[
+                "ZZ........",
+                "TZZ.......",
+                "TT....SS.L",
+                "IITJJJZZOO",
+                "IITTSSZLOO",
+                "IITSSLLLOO",
+            ]),
+        ],
+    },
+    Setup {
+        name: "PCO",
+        aliases: &["perfect clear opener", "pc opener"],
+        rows: &[
+            ".......SSZ",
+            "...OOJSSZZ",
+            "...OOJJJZL",
+            "...IIIILLL",
+        ],
+        follow_ups: &[
+            ("Perfect clear with the held T, T, L and I", &[
+                "TLLIIIISSZ",
+                "TTLOOJSSZZ",
+                "TTLOOJJJZL",
+                "TTTIIIILLL",
+            ]),
+        ],
+    },
+    Setup {
+        name: "MKO",
+        aliases: &["mko stacking"],
+        rows: &[
+            "..Z.....LL",
+            ".ZZ....OOL",
+            "JZ...SSOOL",
+            "JJJ.SSIIII",
+        ],
+        follow_ups: &[
+            ("TSD", &[
+                "..Z.....LL",
+                ".ZZ....OOL",
+                "JZTTTSSOOL",
+                "JJJTSSIIII",
+            ]),
+        ],
+    },
+    Setup {
+        name: "Albatross",
+        aliases: &["albatross special"],
+        rows: &[
+            "...ZJ..S..",
+            "..ZZJ..SS.",
+            "OOZJJ...SL",
+            "OOIIII.LLL",
+        ],
+        follow_ups: &[
+            ("TSD", &[
+                "...ZJ..S..",
+                "..ZZJ..SS.",
+                "OOZJJTTTSL",
+                "OOIIIITLLL",
+            ]),
+        ],
+    },
+];
+
+impl Setup {
+    pub fn field(&self) -> [[CellColor; 10]; 23] {
+        draw(self.rows)
+    }
+
+    /// The setup followed by a page for each follow-up.
+    pub fn fumen(&self) -> Fumen {
+        let mut fumen = Fumen::default();
+        let page = fumen.add_page();
+        page.field = self.field();
+        page.comment = Some(self.name.to_owned());
+        for &(comment, rows) in self.follow_ups {
+            let page = fumen.add_page();
+            page.field = draw(rows);
+            page.comment = Some(format!("{}: {}", self.name, comment));
+        }
+        fumen
+    }
+}
+
+fn draw(rows: &[&str]) -> [[CellColor; 10]; 23] {
+    let mut field = [[CellColor::Empty; 10]; 23];
+    for (i, row) in rows.iter().enumerate() {
+        let y = rows.len() - 1 - i;
+        for (x, c) in row.chars().enumerate() {
+            field[y][x] = match c {
+                'X' => CellColor::Grey,
+                c => from_char(c).map_or(CellColor::Empty, piece_color)
+            };
+        }
+    }
+    field
+}
+
+/// Looks a setup up by name or alias, ignoring case, spaces and dashes.
+pub fn find(name: &str) -> Option<&'static Setup> {
+    let name = simplify(name);
+    SETUPS.iter().find(|s| {
+        simplify(s.name) == name || s.aliases.iter().any(|&a| simplify(a) == name)
+    })
+}
+
+pub fn names() -> Vec<&'static str> {
+    SETUPS.iter().map(|s| s.name).collect()
+}
+
+/// Finds the setup whose filled cells are exactly those of `field`, either way round.
+pub fn recognize(field: &[[CellColor; 10]; 23]) -> Option<&'static Setup> {
+    let filled = occupancy(field);
+    if filled.iter().all(|row| row.iter().all(|&c| !c)) {
+        return None
+    }
+    let mut mirrored = filled;
+    for row in &mut mirrored {
+        row.reverse();
+    }
+    SETUPS.iter().find(|s| {
+        let setup = occupancy(&s.field());
+        setup == filled || setup == mirrored
+    })
+}
+
+fn occupancy(field: &[[CellColor; 10]; 23]) -> [[bool; 10]; 23] {
+    let mut result = [[false; 10]; 23];
+    for y in 0..23 {
+        for x in 0..10 {
+            result[y][x] = field[y][x] != CellColor::Empty;
+        }
+    }
+    result
+}
+
+fn simplify(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
@@ -0,0 +1,289 @@
+use once_cell::sync::Lazy;
+use serenity::builder::{ CreateApplicationCommands, CreateComponents };
+use serenity::model::id::{ ChannelId, MessageId, RoleId };
+use serenity::model::interactions::{ Interaction, InteractionResponseType };
+use serenity::model::interactions::application_command::{
+    ApplicationCommand, ApplicationCommandInteraction, ApplicationCommandOptionType
+};
+use serenity::model::interactions::autocomplete::AutocompleteInteraction;
+use serenity::model::interactions::message_component::{ ButtonStyle, MessageComponentInteraction };
+use serenity::model::permissions::Permissions;
+use serenity::prelude::*;
+use tokio::sync::Mutex;
+
+use super::bounded::BoundedMap;
+use super::evaluator::PRESETS;
+use super::render::THEMES;
+
+/// The speeds offered by the buttons under a render.
+const SPEEDS: &[&str] = &["0.5", "1", "2", "4"];
+/// Renders remembered for their speed buttons; the oldest are forgotten first.
+const MAX_RENDERS: usize = 1000;
+
+/// The text each render was made from, by the message showing it.
+static RENDERS: Lazy<Mutex<BoundedMap<MessageId, String>>> =
+    Lazy::new(|| Mutex::new(BoundedMap::new(MAX_RENDERS)));
+
+pub async fn remember_render(message: MessageId, text: &str) {
+    RENDERS.lock().await.insert(message, text.to_owned());
+}
+
+pub fn speed_buttons(c: &mut CreateComponents) -> &mut CreateComponents {
+    c.create_action_row(|row| {
+        for &speed in SPEEDS {
+            row.create_button(|b| b
+                .style(ButtonStyle::Secondary)
+                .label(format!("{}x", speed))
+                .custom_id(format!("speed:{}", speed))
+            );
+        }
+        row
+    })
+}
+
+/// Registers `/render`, `/cc` and `/config` with Discord, replacing any earlier versions.
+pub async fn register(ctx: &Context) {
+    let result = ApplicationCommand::set_global_application_commands(&ctx.http, commands).await;
+    if let Err(e) = result {
+        println!("Failed to register slash commands: {}", e);
+    }
+}
+
+/// Speeds and rotation systems are fixed choices; theme names and evaluator presets are
+/// autocompleted by `complete`.
+fn commands(c: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    c.create_application_command(|c| c
+        .name("render")
+        .description("Render a fumen as a GIF")
+        .create_option(|o| o
+            .name("fumen")
+            .description("Fumen data or link, with options like #speed=2")
+            .kind(ApplicationCommandOptionType::String)
+            .required(true)
+        )
+        .create_option(|o| {
+            o.name("speed")
+                .description("Frames per half second")
+                .kind(ApplicationCommandOptionType::String);
+            for &speed in SPEEDS {
+                o.add_string_choice(format!("{}x", speed), speed);
+            }
+            o
+        })
+        .create_option(|o| o
+            .name("theme")
+            .description("Colours to draw the board in")
+            .kind(ApplicationCommandOptionType::String)
+            .set_autocomplete(true)
+        )
+    );
+    c.create_application_command(|c| c
+        .name("cc")
+        .description("Analyse a fumen with a #Q= queue comment using Cold Clear")
+        .create_option(|o| o
+            .name("fumen")
+            .description("Fumen data or link, with options like #garbage=3@2")
+            .kind(ApplicationCommandOptionType::String)
+            .required(true)
+        )
+        .create_option(|o| o
+            .name("eval")
+            .description("Evaluator preset")
+            .kind(ApplicationCommandOptionType::String)
+            .set_autocomplete(true)
+        )
+        .create_option(|o| o
+            .name("rs")
+            .description("Rotation system to describe the inputs in")
+            .kind(ApplicationCommandOptionType::String)
+            .add_string_choice("SRS", "srs")
+            .add_string_choice("SRS+", "srsplus")
+            .add_string_choice("ARS", "ars")
+            .add_string_choice("NRS", "nrs")
+        )
+        .create_option(|o| o
+            .name("json")
+            .description("Attach the placements as JSON")
+            .kind(ApplicationCommandOptionType::Boolean)
+        )
+    );
+    c.create_application_command(|c| c
+        .name("config")
+        .description("Change this server's settings (administrators only)")
+        .create_option(|o| o
+            .name("muted_role")
+            .description("Role given to members muted for scams")
+            .kind(ApplicationCommandOptionType::Role)
+        )
+        .create_option(|o| o
+            .name("admin_channel")
+            .description("Channel for moderation notices")
+            .kind(ApplicationCommandOptionType::Channel)
+        )
+        .create_option(|o| o
+            .name("puzzle_channel")
+            .description("Channel the daily puzzle is posted in")
+            .kind(ApplicationCommandOptionType::Channel)
+        )
+    )
+}
+
+pub async fn interaction(ctx: &Context, interaction: Interaction) {
+    let result = match interaction {
+        Interaction::ApplicationCommand(command) => match command.data.name.as_str() {
+            "render" => render(ctx, &command).await,
+            "cc" => cold_clear(ctx, &command).await,
+            "config" => config(ctx, &command).await,
+            _ => Ok(())
+        },
+        Interaction::MessageComponent(component) => rerender(ctx, &component).await,
+        Interaction::Autocomplete(autocomplete) => complete(ctx, &autocomplete).await,
+        _ => Ok(())
+    };
+    if let Err(e) = result {
+        println!("Failed to handle interaction: {}", e);
+    }
+}
+
+/// Suggests the theme names or evaluator presets starting with what has been typed so far.
+async fn complete(ctx: &Context, autocomplete: &AutocompleteInteraction) -> serenity::Result<()> {
+    let focused = match autocomplete.data.options.iter().find(|o| o.focused) {
+        Some(focused) => focused,
+        None => return Ok(())
+    };
+    let names: Vec<&str> = match focused.name.as_str() {
+        "theme" => THEMES.iter().map(|&(name, _)| name).collect(),
+        "eval" => PRESETS.to_vec(),
+        _ => return Ok(())
+    };
+    let typed = match &focused.value {
+        Some(serde_json::Value::String(typed)) => typed.to_ascii_lowercase(),
+        _ => String::new()
+    };
+    autocomplete.create_autocomplete_response(&ctx.http, |r| {
+        for name in names.into_iter().filter(|name| name.starts_with(&typed)).take(25) {
+            r.add_string_choice(name, name);
+        }
+        r
+    }).await
+}
+
+/// The value of a string, boolean or id option, as text.
+fn option(command: &ApplicationCommandInteraction, name: &str) -> Option<String> {
+    let value = command.data.options.iter().find(|o| o.name == name)?.value.as_ref()?;
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        value => Some(value.to_string())
+    }
+}
+
+async fn respond(
+    ctx: &Context, command: &ApplicationCommandInteraction, text: &str
+) -> serenity::Result<()> {
+    command.create_interaction_response(&ctx.http, |r| r
+        .kind(InteractionResponseType::ChannelMessageWithSource)
+        .interaction_response_data(|d| d.content(text))
+    ).await
+}
+
+async fn render(ctx: &Context, command: &ApplicationCommandInteraction) -> serenity::Result<()> {
+    let mut text = option(command, "fumen").unwrap_or_default().trim().to_owned();
+    if let Some(theme) = option(command, "theme") {
+        let separator = if text.contains('#') { '&' } else { '#' };
+        text += &format!("{}theme={}", separator, theme);
+    }
+    let speed = option(command, "speed").and_then(|s| s.parse().ok());
+    command.create_interaction_response(&ctx.http, |r| r
+        .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+    ).await?;
+    let rendered = match super::render_text(&text, speed).await {
+        Some(rendered) => rendered,
+        None => {
+            command.create_followup_message(&ctx.http, |f| f.content("Invalid fumen")).await?;
+            return Ok(())
+        }
+    };
+    let reply = command.create_followup_message(&ctx.http, |f| {
+        f.add_file(rendered.attachment()).components(speed_buttons);
+        if let Some(content) = rendered.content {
+            f.content(content);
+        }
+        f
+    }).await?;
+    remember_render(reply.id, &text).await;
+    Ok(())
+}
+
+/// Posts the render again at the speed of the button that was pressed.
+async fn rerender(ctx: &Context, component: &MessageComponentInteraction) -> serenity::Result<()> {
+    let speed = match component.data.custom_id.strip_prefix("speed:") {
+        Some(speed) => speed.parse().ok(),
+        None => return Ok(())
+    };
+    let text = RENDERS.lock().await.get(&component.message.id()).cloned();
+    let text = match text {
+        Some(text) => text,
+        None => {
+            component.create_interaction_response(&ctx.http, |r| r
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| d.content("I no longer remember that fumen."))
+            ).await?;
+            return Ok(())
+        }
+    };
+    component.create_interaction_response(&ctx.http, |r| r
+        .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+    ).await?;
+    if let Some(rendered) = super::render_text(&text, speed).await {
+        let reply = component.create_followup_message(&ctx.http, |f| f
+            .add_file(rendered.attachment())
+            .components(speed_buttons)
+        ).await?;
+        remember_render(reply.id, &text).await;
+    }
+    Ok(())
+}
+
+async fn cold_clear(
+    ctx: &Context, command: &ApplicationCommandInteraction
+) -> serenity::Result<()> {
+    let mut text = option(command, "fumen").unwrap_or_default();
+    let mut separator = if text.contains('#') { '&' } else { '#' };
+    for name in ["eval", "rs"] {
+        if let Some(value) = option(command, name) {
+            text += &format!("{}{}={}", separator, name, value);
+            separator = '&';
+        }
+    }
+    if option(command, "json").as_deref() == Some("true") {
+        text += &format!("{}json=yes", separator);
+    }
+    respond(ctx, command, &format!("Analysing `{}`", text)).await?;
+    let user = command.user.id;
+    if let Err(e) = super::analyse(ctx, command.channel_id, user, &text).await {
+        println!("Analysis failed: {}", e);
+    }
+    Ok(())
+}
+
+async fn config(ctx: &Context, command: &ApplicationCommandInteraction) -> serenity::Result<()> {
+    let (guild, member) = match (command.guild_id, &command.member) {
+        (Some(guild), Some(member)) => (guild, member),
+        _ => return respond(ctx, command, "This only works in servers.").await
+    };
+    let admin = member.permissions.map_or(false, |p| p.contains(Permissions::ADMINISTRATOR));
+    if !admin {
+        return respond(ctx, command, "Only administrators can change the settings.").await
+    }
+    let id = |name| option(command, name).and_then(|v| v.parse::<u64>().ok());
+    if let Some(role) = id("muted_role") {
+        crate::store_muted_role(guild, RoleId(role)).await;
+    }
+    if let Some(channel) = id("admin_channel") {
+        crate::store_admin_channel(guild, ChannelId(channel)).await;
+    }
+    if let Some(channel) = id("puzzle_channel") {
+        crate::store_puzzle_channel(guild, ChannelId(channel)).await;
+    }
+    respond(ctx, command, "Settings saved.").await
+}
@@ -0,0 +1,108 @@
+use libtetris::{ LockResult, PlacementKind };
+
+/// Running totals for a line of play, used to annotate analysis pages.
+#[derive(Default)]
+pub struct Stats {
+    pieces: u32,
+    attack: u32,
+    lines: u32,
+    b2b_chain: u32,
+    perfect_clears: u32,
+    clears: Vec<(&'static str, u32)>,
+}
+
+impl Stats {
+    /// Records a placement and describes it, e.g. `T-Spin Double B2B x3, combo 2, 4 lines sent`.
+    pub fn record(&mut self, lock: &LockResult) -> Option<String> {
+        self.pieces += 1;
+        self.attack += lock.garbage_sent;
+        self.lines += lock.cleared_lines.len() as u32;
+
+        let name = clear_name(lock.placement_kind)?;
+        match self.clears.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, count)) => *count += 1,
+            None => self.clears.push((name, 1))
+        }
+        if lock.b2b {
+            self.b2b_chain += 1;
+        } else if !lock.cleared_lines.is_empty() {
+            self.b2b_chain = 0;
+        }
+
+        let mut text = name.to_owned();
+        if lock.b2b {
+            text += &format!(" B2B x{}", self.b2b_chain);
+        }
+        if lock.perfect_clear {
+            self.perfect_clears += 1;
+            text += " Perfect Clear";
+        }
+        if let Some(combo) = lock.combo.filter(|&c| c > 0) {
+            text += &format!(", combo {}", combo);
+        }
+        if lock.garbage_sent > 0 {
+            text += &format!(", {} lines sent", lock.garbage_sent);
+        }
+        Some(text)
+    }
+
+    /// Totals for the whole line, e.g. `14 pieces, 12 attack (0.86 APP), 2x T-Spin Double`.
+    pub fn summary(&self) -> String {
+        let app = match self.pieces {
+            0 => 0.0,
+            p => self.attack as f64 / p as f64
+        };
+        let mut text = format!(
+            "{} pieces, {} lines cleared, {} attack ({:.2} APP)",
+            self.pieces, self.lines, self.attack, app
+        );
+        if self.lines > 0 {
+            text += &format!(", {:.2} pieces per line", self.pieces as f64 / self.lines as f64);
+        }
+        for (name, count) in &self.clears {
+            text += &format!(", {}x {}", count, name);
+        }
+        if self.perfect_clears > 0 {
+            text += &format!(", {}x Perfect Clear", self.perfect_clears);
+        }
+        text
+    }
+}
+
+fn clear_name(kind: PlacementKind) -> Option<&'static str> {
+    Some(match kind {
+        PlacementKind::None => return None,
+        PlacementKind::Clear1 => "Single",
+        PlacementKind::Clear2 => "Double",
+        PlacementKind::Clear3 => "Triple",
+        PlacementKind::Clear4 => "Tetris",
+        PlacementKind::MiniTspin => "T-Spin Mini",
+        PlacementKind::MiniTspin1 => "T-Spin Mini Single",
+        PlacementKind::MiniTspin2 => "T-Spin Mini Double",
+        PlacementKind::Tspin => "T-Spin",
+        PlacementKind::Tspin1 => "T-Spin Single",
+        PlacementKind::Tspin2 => "T-Spin Double",
+        PlacementKind::Tspin3 => "T-Spin Triple",
+    })
+}
+
+const COMBO_ATTACK: [u32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+
+/// Lines sent by a placement under the guideline attack table, including back-to-back, combo
+/// and perfect clear bonuses.
+pub fn guideline_attack(lock: &LockResult) -> u32 {
+    let base = match lock.placement_kind {
+        PlacementKind::None | PlacementKind::Clear1 => 0,
+        PlacementKind::MiniTspin | PlacementKind::MiniTspin1 | PlacementKind::Tspin => 0,
+        PlacementKind::Clear2 | PlacementKind::MiniTspin2 => 1,
+        PlacementKind::Clear3 | PlacementKind::Tspin1 => 2,
+        PlacementKind::Clear4 | PlacementKind::Tspin2 => 4,
+        PlacementKind::Tspin3 => 6,
+    };
+    let b2b = lock.b2b as u32;
+    let combo = lock.combo
+        .map(|c| COMBO_ATTACK[(c as usize).min(COMBO_ATTACK.len() - 1)])
+        .unwrap_or(0);
+    let perfect_clear = if lock.perfect_clear { 10 } else { 0 };
+    base + b2b + combo + perfect_clear
+}
@@ -0,0 +1,512 @@
+use fumen::{ CellColor, Fumen };
+use libtetris::{ Board, FallingPiece, Piece };
+use serde_json::Value;
+
+use super::finesse::Input;
+use super::{ lock_colors, raise_garbage };
+use super::replay::MAX_REPLAY_PIECES;
+use super::rotation::RotationSystem;
+use super::stats::guideline_attack;
+
+/// TETR.IO's lock delay and the number of times moving a grounded piece can reset it.
+const LOCK_DELAY: u32 = 30;
+const LOCK_RESETS: u32 = 15;
+/// Soft drop factor TETR.IO treats as instant.
+const INSTANT_SDF: f64 = 41.0;
+
+/// The randomizer TETR.IO seeds every game with, a Park–Miller generator.
+struct Prng(u64);
+
+impl Prng {
+    fn new(seed: u64) -> Prng {
+        match seed % 2147483647 {
+            0 => Prng(2147483646),
+            seed => Prng(seed)
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0 * 16807 % 2147483647;
+        self.0
+    }
+
+    fn next_float(&mut self) -> f64 {
+        (self.next() - 1) as f64 / 2147483646.0
+    }
+}
+
+/// TETR.IO's 7-bag, which shuffles `ZLOSIJT` with its generator.
+struct Bag {
+    rng: Prng,
+    remaining: Vec<Piece>,
+}
+
+impl Bag {
+    fn next(&mut self) -> Piece {
+        if self.remaining.is_empty() {
+            let mut bag = vec![
+                Piece::Z, Piece::L, Piece::O, Piece::S, Piece::I, Piece::J, Piece::T
+            ];
+            for i in (1..bag.len()).rev() {
+                let j = (self.rng.next_float() * (i + 1) as f64) as usize;
+                bag.swap(i, j);
+            }
+            bag.reverse();
+            self.remaining = bag;
+        }
+        self.remaining.pop().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Key {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    Cw,
+    Ccw,
+    Flip,
+    Hold,
+}
+
+impl Key {
+    fn parse(name: &str) -> Option<Key> {
+        Some(match name {
+            "moveLeft" => Key::Left,
+            "moveRight" => Key::Right,
+            "softDrop" => Key::SoftDrop,
+            "hardDrop" => Key::HardDrop,
+            "rotateCW" => Key::Cw,
+            "rotateCCW" => Key::Ccw,
+            "rotate180" => Key::Flip,
+            "hold" => Key::Hold,
+            _ => return None
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Event {
+    KeyDown(Key),
+    KeyUp(Key),
+    Garbage { amount: u32, column: usize },
+}
+
+struct Settings {
+    seed: u64,
+    das: f64,
+    arr: f64,
+    sdf: f64,
+    gravity: f64,
+}
+
+/// One player's game from a replay file.
+pub struct Replay {
+    pub player: String,
+    settings: Settings,
+    /// Events by frame, with subframes as the fractional part, in the order they happened.
+    events: Vec<(f64, Event)>,
+}
+
+/// Reads the games in a `.ttr` (singleplayer) or `.ttrm` (multiplayer, one per player and round)
+/// replay.
+pub fn parse(data: &[u8]) -> Result<Vec<Replay>, String> {
+    let json: Value = serde_json::from_slice(data)
+        .map_err(|e| format!("not a TETR.IO replay: {}", e))?;
+    let mut replays = vec![];
+    match &json["data"] {
+        // A .ttrm lists its rounds, each with a replay and a board per player.
+        Value::Array(rounds) => for round in rounds {
+            let players = round["replays"].as_array().ok_or("a round has no replays")?;
+            for (i, replay) in players.iter().enumerate() {
+                let board = &round["board"][i];
+                let player = board["user"]["username"].as_str()
+                    .or_else(|| board["username"].as_str())
+                    .or_else(|| json["endcontext"][i]["user"]["username"].as_str());
+                replays.push(parse_game(&replay["events"], player)?);
+            }
+        }
+        // A .ttr holds the events of its one game directly.
+        game => {
+            let player = json["user"]["username"].as_str()
+                .or_else(|| json["endcontext"]["username"].as_str());
+            replays.push(parse_game(&game["events"], player)?);
+        }
+    }
+    if replays.is_empty() {
+        return Err("the replay has no games".to_owned())
+    }
+    Ok(replays)
+}
+
+fn parse_game(events: &Value, player: Option<&str>) -> Result<Replay, String> {
+    let events = events.as_array().ok_or("a game has no events")?;
+    let options = events.iter()
+        .find(|e| e["type"] == "full")
+        .map(|e| &e["data"]["options"])
+        .ok_or("a game has no starting state")?;
+    let player = player.or_else(|| options["username"].as_str()).unwrap_or("unknown");
+    let handling = &options["handling"];
+    let settings = Settings {
+        seed: options["seed"].as_f64().ok_or("a game has no seed")? as u64,
+        das: handling["das"].as_f64().unwrap_or(10.0),
+        arr: handling["arr"].as_f64().unwrap_or(2.0),
+        sdf: handling["sdf"].as_f64().unwrap_or(6.0),
+        gravity: options["g"].as_f64().unwrap_or(0.02),
+    };
+
+    let mut parsed = vec![];
+    for event in events {
+        let frame = event["frame"].as_f64().unwrap_or(0.0);
+        let data = &event["data"];
+        let subframe = data["subframe"].as_f64().unwrap_or(0.0);
+        let key = data["key"].as_str().and_then(Key::parse);
+        let event = match (event["type"].as_str(), key) {
+            (Some("keydown"), Some(key)) => Event::KeyDown(key),
+            (Some("keyup"), Some(key)) => Event::KeyUp(key),
+            (Some("ige"), _) if data["data"]["type"] == "garbage" => Event::Garbage {
+                amount: data["data"]["amt"].as_u64().unwrap_or(0) as u32,
+                column: data["data"]["column"].as_u64().unwrap_or(0).min(9) as usize,
+            },
+            _ => continue
+        };
+        parsed.push((frame + subframe, event));
+    }
+    parsed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    Ok(Replay { player: player.to_owned(), settings, events: parsed })
+}
+
+/// Replays the key presses of a game with TETR.IO's randomizer and SRS+, returning a fumen with
+/// a page for each placement.
+///
+/// Handling follows the replay's DAS, ARR, soft drop factor and gravity, and grounded pieces lock
+/// after TETR.IO's lock delay. Incoming garbage is cancelled by the guideline attack of each
+/// placement and rises when a piece locks without clearing lines.
+pub fn simulate(replay: &Replay) -> Fumen {
+    let mut game = Game::new(&replay.settings);
+    for &(time, event) in &replay.events {
+        while game.frame + 1.0 <= time && !game.over {
+            game.tick();
+        }
+        if game.over || game.pages.len() >= MAX_REPLAY_PIECES {
+            break
+        }
+        game.frame = game.frame.max(time);
+        game.handle(event);
+    }
+
+    let mut fumen = Fumen::default();
+    for (field, piece) in game.pages {
+        let page = fumen.add_page();
+        page.field = field;
+        page.piece = Some(super::to_fumen(piece));
+    }
+    if fumen.pages.is_empty() {
+        fumen.add_page();
+    }
+    fumen
+}
+
+struct Game<'a> {
+    settings: &'a Settings,
+    board: Board,
+    colors: [[CellColor; 10]; 40],
+    bag: Bag,
+    piece: Option<FallingPiece>,
+    held: bool,
+    hold: Option<Piece>,
+    frame: f64,
+    left: bool,
+    right: bool,
+    /// The direction pressed last, which wins while both are held.
+    direction: i32,
+    charge: f64,
+    arr_timer: f64,
+    soft_drop: bool,
+    fall: f64,
+    lock_timer: u32,
+    lock_resets: u32,
+    pending: Vec<(u32, usize)>,
+    pages: Vec<([[CellColor; 10]; 23], FallingPiece)>,
+    over: bool,
+}
+
+impl<'a> Game<'a> {
+    fn new(settings: &'a Settings) -> Game<'a> {
+        let mut game = Game {
+            settings,
+            board: Board::new(),
+            colors: [[CellColor::Empty; 10]; 40],
+            bag: Bag { rng: Prng::new(settings.seed), remaining: vec![] },
+            piece: None,
+            held: false,
+            hold: None,
+            frame: 0.0,
+            left: false,
+            right: false,
+            direction: 0,
+            charge: 0.0,
+            arr_timer: 0.0,
+            soft_drop: false,
+            fall: 0.0,
+            lock_timer: 0,
+            lock_resets: 0,
+            pending: vec![],
+            pages: vec![],
+            over: false,
+        };
+        let first = game.bag.next();
+        game.spawn(first);
+        game
+    }
+
+    fn spawn(&mut self, piece: Piece) {
+        self.piece = RotationSystem::SrsPlus.spawn(piece, &self.board);
+        self.fall = 0.0;
+        self.lock_timer = 0;
+        self.lock_resets = 0;
+        if self.piece.is_none() {
+            self.over = true;
+        } else if self.direction != 0 && self.charge >= self.settings.das {
+            // Held DAS carries over to the new piece.
+            self.auto_shift();
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::KeyDown(Key::Left) => {
+                self.left = true;
+                self.direction = -1;
+                self.charge = 0.0;
+                self.shift(-1);
+            }
+            Event::KeyDown(Key::Right) => {
+                self.right = true;
+                self.direction = 1;
+                self.charge = 0.0;
+                self.shift(1);
+            }
+            Event::KeyUp(Key::Left) => {
+                self.left = false;
+                self.direction = if self.right { 1 } else { 0 };
+            }
+            Event::KeyUp(Key::Right) => {
+                self.right = false;
+                self.direction = if self.left { -1 } else { 0 };
+            }
+            Event::KeyDown(Key::SoftDrop) => self.soft_drop = true,
+            Event::KeyUp(Key::SoftDrop) => self.soft_drop = false,
+            Event::KeyDown(Key::HardDrop) => {
+                if let Some(mut piece) = self.piece {
+                    piece.sonic_drop(&self.board);
+                    self.lock(piece);
+                }
+            }
+            Event::KeyDown(key @ Key::Cw) |
+            Event::KeyDown(key @ Key::Ccw) |
+            Event::KeyDown(key @ Key::Flip) => {
+                let input = match key {
+                    Key::Cw => Input::Cw,
+                    Key::Ccw => Input::Ccw,
+                    _ => Input::Flip
+                };
+                if let Some(mut piece) = self.piece {
+                    if input.apply(&mut piece, &self.board, RotationSystem::SrsPlus) {
+                        self.moved(piece);
+                    }
+                }
+            }
+            Event::KeyDown(Key::Hold) => {
+                if let (Some(piece), false) = (self.piece, self.held) {
+                    let next = match self.hold.replace(piece.kind.0) {
+                        Some(held) => held,
+                        None => self.bag.next()
+                    };
+                    self.spawn(next);
+                    self.held = true;
+                }
+            }
+            Event::KeyUp(_) => {}
+            Event::Garbage { amount, column } => self.pending.push((amount, column)),
+        }
+    }
+
+    /// Advances a frame of DAS, soft drop, gravity and lock delay.
+    fn tick(&mut self) {
+        self.frame += 1.0;
+        if self.direction != 0 {
+            self.charge += 1.0;
+            if self.charge >= self.settings.das {
+                if self.settings.arr <= 0.0 {
+                    self.auto_shift();
+                } else if self.charge - 1.0 < self.settings.das {
+                    self.arr_timer = 0.0;
+                    self.shift(self.direction);
+                } else {
+                    self.arr_timer += 1.0;
+                    while self.arr_timer >= self.settings.arr {
+                        self.arr_timer -= self.settings.arr;
+                        self.shift(self.direction);
+                    }
+                }
+            }
+        }
+
+        let mut piece = match self.piece {
+            Some(piece) => piece,
+            None => return
+        };
+        if self.soft_drop && self.settings.sdf >= INSTANT_SDF {
+            if piece.sonic_drop(&self.board) {
+                self.moved(piece);
+            }
+        } else {
+            let speed = if self.soft_drop {
+                self.settings.gravity * self.settings.sdf
+            } else {
+                self.settings.gravity
+            };
+            self.fall += speed;
+            while self.fall >= 1.0 {
+                self.fall -= 1.0;
+                if !piece.shift(&self.board, 0, -1) {
+                    self.fall = 0.0;
+                    break
+                }
+                self.piece = Some(piece);
+            }
+        }
+
+        let mut below = piece;
+        if !below.shift(&self.board, 0, -1) {
+            self.lock_timer += 1;
+            if self.lock_timer >= LOCK_DELAY {
+                self.lock(piece);
+            }
+        }
+    }
+
+    fn shift(&mut self, dx: i32) {
+        if let Some(mut piece) = self.piece {
+            if piece.shift(&self.board, dx, 0) {
+                self.moved(piece);
+            }
+        }
+    }
+
+    fn auto_shift(&mut self) {
+        if let Some(mut piece) = self.piece {
+            let mut moved = false;
+            while piece.shift(&self.board, self.direction, 0) {
+                moved = true;
+            }
+            if moved {
+                self.moved(piece);
+            }
+        }
+    }
+
+    /// Keeps a successful move, resetting lock delay a limited number of times.
+    fn moved(&mut self, piece: FallingPiece) {
+        self.piece = Some(piece);
+        if self.lock_timer > 0 && self.lock_resets < LOCK_RESETS {
+            self.lock_timer = 0;
+            self.lock_resets += 1;
+        }
+    }
+
+    fn lock(&mut self, piece: FallingPiece) {
+        let mut field = [[CellColor::Empty; 10]; 23];
+        field.copy_from_slice(&self.colors[..23]);
+        self.pages.push((field, piece));
+
+        let lock = self.board.lock_piece(piece);
+        lock_colors(&mut self.colors, piece, &lock.cleared_lines);
+        let mut attack = guideline_attack(&lock);
+        while attack > 0 && !self.pending.is_empty() {
+            let cancelled = attack.min(self.pending[0].0);
+            attack -= cancelled;
+            self.pending[0].0 -= cancelled;
+            if self.pending[0].0 == 0 {
+                self.pending.remove(0);
+            }
+        }
+        if lock.cleared_lines.is_empty() {
+            for (amount, column) in std::mem::take(&mut self.pending) {
+                if raise_garbage(&mut self.board, &mut self.colors, amount as usize, column) {
+                    self.over = true;
+                }
+            }
+        }
+
+        self.held = false;
+        if lock.locked_out {
+            self.over = true;
+        } else {
+            let next = self.bag.next();
+            self.spawn(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libtetris::{ PieceState, RotationState };
+
+    fn placements(fumen: &Fumen) -> Vec<FallingPiece> {
+        fumen.pages.iter().filter_map(|page| page.piece).map(super::super::to_libtetris).collect()
+    }
+
+    #[test]
+    fn prng_sequence() {
+        let mut rng = Prng::new(1);
+        assert_eq!(rng.next(), 16807);
+        assert_eq!(rng.next(), 282475249);
+        assert_eq!(rng.next(), 1622650073);
+        assert_eq!(Prng::new(0).0, 2147483646);
+        assert_eq!(Prng::new(2147483647).0, 2147483646);
+    }
+
+    #[test]
+    fn bag_sequence() {
+        let mut bag = Bag { rng: Prng::new(4242), remaining: vec![] };
+        let pieces: Vec<_> = (0..14).map(|_| bag.next()).collect();
+        use Piece::*;
+        assert_eq!(pieces, [T, I, S, O, L, J, Z, L, Z, J, T, O, I, S]);
+    }
+
+    #[test]
+    fn singleplayer_replay() {
+        let replays = parse(include_bytes!("testdata/sample.ttr")).unwrap();
+        assert_eq!(replays.len(), 1);
+        assert_eq!(replays[0].player, "alice");
+        let fumen = simulate(&replays[0]);
+        let pieces = placements(&fumen);
+        assert_eq!(pieces.len(), 3);
+
+        assert_eq!(pieces[0].kind, PieceState(Piece::T, RotationState::North));
+        assert_eq!((pieces[0].x, pieces[0].y), (4, 0));
+        // Tapped left once, then dropped onto the T's point.
+        assert_eq!(pieces[1].kind, PieceState(Piece::I, RotationState::North));
+        assert_eq!((pieces[1].x, pieces[1].y), (3, 2));
+        assert_eq!(pieces[2].kind, PieceState(Piece::S, RotationState::East));
+        assert_eq!(pieces[2].x, 4);
+
+        assert_eq!(fumen.pages[1].field[0][3..6], [CellColor::T; 3]);
+        assert_eq!(fumen.pages[2].field[2][2..6], [CellColor::I; 4]);
+    }
+
+    #[test]
+    fn multiplayer_replay() {
+        let replays = parse(include_bytes!("testdata/sample.ttrm")).unwrap();
+        let players: Vec<_> = replays.iter().map(|r| r.player.as_str()).collect();
+        assert_eq!(players, ["alice", "bob"]);
+        assert_eq!(placements(&simulate(&replays[0])).len(), 3);
+        let bob = placements(&simulate(&replays[1]));
+        assert_eq!(bob.len(), 1);
+        assert_eq!(bob[0].kind.0, Piece::O);
+    }
+}
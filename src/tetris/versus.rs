@@ -0,0 +1,209 @@
+use cold_clear::evaluation::Standard;
+use fumen::CellColor;
+use libtetris::{ Board, FallingPiece, LockResult };
+
+use super::{ lock_colors, raise_garbage };
+use super::garbage::{ draw_meter, METER_WIDTH };
+use super::queue::{ Bag, Rng };
+use super::render::{ self, Canvas, BLOCK_SIZE };
+use super::stats::guideline_attack;
+
+/// Pieces each bot is shown ahead of the current piece.
+const PREVIEWS: usize = 5;
+/// Most garbage lines that rise after a single placement; the rest stays pending.
+const GARBAGE_CAP: u32 = 8;
+const GAP: usize = BLOCK_SIZE / 2;
+
+/// A game between two Cold Clear instances dealt the same seeded 7-bag queue.
+pub struct Game {
+    players: [Player; 2],
+    pub frames: Vec<Frame>,
+    pub pieces: usize,
+}
+
+/// Both boards after a round of placements.
+pub struct Frame {
+    fields: [[[CellColor; 10]; 23]; 2],
+    pending: [u32; 2],
+}
+
+struct Player {
+    cc: cold_clear::Interface,
+    board: Board,
+    colors: [[CellColor; 10]; 40],
+    bag: Bag,
+    holes: Rng,
+    queued: usize,
+    held: bool,
+    pending: u32,
+    attack: u32,
+    lines: u32,
+    pieces: u32,
+    topped_out: bool,
+}
+
+impl Player {
+    fn new(seed: u64, index: u64, evaluator: Standard) -> Player {
+        let board = Board::new();
+        let cc = cold_clear::Interface::launch(board.clone(), cold_clear::Options {
+            pcloop: None,
+            ..Default::default()
+        }, evaluator, None);
+        let mut bag = Bag::new(seed);
+        for _ in 0..PREVIEWS + 1 {
+            cc.add_next_piece(bag.next());
+        }
+        Player {
+            cc,
+            board,
+            colors: [[CellColor::Empty; 10]; 40],
+            bag,
+            holes: Rng::new(seed ^ (index + 1).wrapping_mul(0x5DEECE66D)),
+            queued: PREVIEWS + 1,
+            held: false,
+            pending: 0,
+            attack: 0,
+            lines: 0,
+            pieces: 0,
+            topped_out: false,
+        }
+    }
+
+    /// Plays Cold Clear's move and returns the attack it sends after cancelling incoming garbage.
+    fn play(&mut self, mv: libtetris::Move) -> u32 {
+        self.cc.play_next_move(mv.expected_location);
+        self.queued -= 1;
+        if mv.hold && !self.held {
+            self.held = true;
+            self.queued -= 1;
+        }
+        while self.queued < PREVIEWS + 1 {
+            self.cc.add_next_piece(self.bag.next());
+            self.queued += 1;
+        }
+
+        let lock = self.lock(mv.expected_location);
+        self.pieces += 1;
+        self.lines += lock.cleared_lines.len() as u32;
+        if lock.locked_out {
+            self.topped_out = true;
+        }
+        let attack = guideline_attack(&lock);
+        self.attack += attack;
+        let cancelled = attack.min(self.pending);
+        self.pending -= cancelled;
+        if lock.cleared_lines.is_empty() && self.pending > 0 {
+            let lines = self.pending.min(GARBAGE_CAP);
+            self.pending -= lines;
+            self.add_garbage(lines);
+        }
+        attack - cancelled
+    }
+
+    fn lock(&mut self, piece: FallingPiece) -> LockResult {
+        let lock = self.board.lock_piece(piece);
+        lock_colors(&mut self.colors, piece, &lock.cleared_lines);
+        lock
+    }
+
+    fn add_garbage(&mut self, lines: u32) {
+        let hole = self.holes.below(10);
+        if raise_garbage(&mut self.board, &mut self.colors, lines as usize, hole) {
+            self.topped_out = true;
+        }
+        let field = self.board.get_field();
+        self.cc.reset(field, self.board.b2b_bonus, self.board.combo);
+    }
+
+    fn visible_field(&self) -> [[CellColor; 10]; 23] {
+        let mut field = [[CellColor::Empty; 10]; 23];
+        field.copy_from_slice(&self.colors[..23]);
+        field
+    }
+
+    fn summary(&self) -> String {
+        let app = match self.pieces {
+            0 => 0.0,
+            p => self.attack as f64 / p as f64
+        };
+        format!(
+            "{} pieces, {} lines, {} attack ({:.2} APP){}",
+            self.pieces, self.lines, self.attack, app,
+            if self.topped_out { ", topped out" } else { "" }
+        )
+    }
+}
+
+impl Game {
+    pub fn new(seed: u64, evaluators: [Standard; 2]) -> Game {
+        let [first, second] = evaluators;
+        Game {
+            players: [Player::new(seed, 0, first), Player::new(seed, 1, second)],
+            frames: vec![],
+            pieces: 0,
+        }
+    }
+
+    /// Has both bots place a piece, returning `false` once one of them has topped out.
+    pub async fn step(&mut self) -> bool {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        for player in &self.players {
+            player.cc.suggest_next_move(player.pending);
+        }
+        tokio::task::yield_now().await;
+        let mut sent = [0; 2];
+        for (i, player) in self.players.iter_mut().enumerate() {
+            match player.cc.block_next_move() {
+                Some((mv, _)) => sent[i] = player.play(mv),
+                None => player.topped_out = true
+            }
+        }
+        self.players[0].pending += sent[1];
+        self.players[1].pending += sent[0];
+        self.pieces += 1;
+        self.frames.push(Frame {
+            fields: [self.players[0].visible_field(), self.players[1].visible_field()],
+            pending: [self.players[0].pending, self.players[1].pending],
+        });
+        !self.players.iter().any(|p| p.topped_out)
+    }
+
+    pub fn summary(&self, names: [&str; 2]) -> String {
+        let result = match (self.players[0].topped_out, self.players[1].topped_out) {
+            (false, true) => format!("P1 ({}) wins", names[0]),
+            (true, false) => format!("P2 ({}) wins", names[1]),
+            (true, true) => "Both bots topped out".to_owned(),
+            (false, false) => format!("No winner after {} pieces", self.pieces),
+        };
+        format!(
+            "{}.\nP1 ({}): {}\nP2 ({}): {}",
+            result, names[0], self.players[0].summary(), names[1], self.players[1].summary()
+        )
+    }
+}
+
+/// Renders both boards side by side, each with an incoming garbage meter on its left.
+pub fn render(frames: &[Frame], speed: f64) -> Result<Vec<u8>, gif::EncodingError> {
+    let rows = frames.iter()
+        .flat_map(|f| f.fields.iter().map(|field| render::field_rows(field, None)))
+        .max()
+        .unwrap_or(1)
+        .max(4);
+    let board_width = METER_WIDTH + BLOCK_SIZE * 10;
+    let width = board_width * 2 + GAP;
+    let height = render::pixel_height(rows, false);
+    let mut gif_data = vec![];
+    let mut writer = render::encoder(&mut gif_data, width, height)?;
+    for frame in frames {
+        let mut canvas = Canvas::new(width, rows, false);
+        canvas.fill_rect(0, 0, width, height, render::BACKGROUND);
+        for i in 0..2 {
+            let left = i * (board_width + GAP);
+            canvas.draw_field(left + METER_WIDTH, &frame.fields[i], None, None);
+            draw_meter(&mut canvas, left, frame.pending[i]);
+        }
+        writer.write_frame(&canvas.into_frame(speed))?;
+    }
+    drop(writer);
+    Ok(gif_data)
+}